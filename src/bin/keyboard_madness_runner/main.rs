@@ -0,0 +1,908 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+
+use clap::{builder::PossibleValuesParser, Args, Parser, ValueEnum};
+use keyboard_madness::{
+    read_instructions, EdgePolicy, Event, GenerateOptions, Instruction, Keyboard, KeyboardError,
+    KeyboardLayout, Keystrokes, LayoutFormat, Manhattan, NullSink, OutputSink, ParseMode, Position,
+    Program, Severity, Spanned, Stats, TokenCount, UntypeablePolicy, WriteSink,
+};
+use repl::Repl;
+use serde::Serialize;
+#[cfg(not(target_family = "wasm"))]
+use tui::Tui;
+
+mod repl;
+#[cfg(not(target_family = "wasm"))]
+mod tui;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct KeyboardMadness {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Parser, Debug)]
+enum Command {
+    Run(RunArgs),
+    Generate(GenerateArgs),
+    Optimize(OptimizeArgs),
+    Trace(TraceArgs),
+    Stats(StatsArgs),
+    Repl(ReplArgs),
+    Validate(ValidateArgs),
+    #[cfg(not(target_family = "wasm"))]
+    Tui(TuiArgs),
+}
+
+/// Run instructions on the keyboard
+#[derive(Parser, Debug)]
+#[command(name = "run", author, version, about, long_about = None)]
+struct RunArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// Reject unknown instructions instead of ignoring them
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the output as text, or as a JSON object along with where the cursor ended up
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Redraw the keyboard after every instruction instead of only printing the output
+    #[arg(long, conflicts_with_all = ["output", "format"])]
+    animate: bool,
+
+    /// Milliseconds to wait between instructions when animating
+    #[arg(long, default_value_t = 250, requires = "animate")]
+    delay: u64,
+
+    /// Read the instructions from a file, or - for standard input, running them as they arrive
+    #[arg(long, value_name = "FILE", conflicts_with = "instructions")]
+    file: Option<PathBuf>,
+
+    /// Run the sample instructions, which type HELLO
+    #[arg(long, conflicts_with_all = ["instructions", "file"])]
+    demo: bool,
+
+    /// Write the output to a file instead of standard output
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Instructions to execute, or - to read them from standard input as they arrive
+    #[arg(required_unless_present_any = ["file", "demo"])]
+    instructions: Option<String>,
+}
+
+/// Generate instructions
+#[derive(Parser, Debug)]
+#[command(name = "generate", author, version, about, long_about = None)]
+struct GenerateArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// What to minimize: button presses (keystrokes), instructions (tokens) or cursor travel (distance)
+    #[arg(long, value_enum, default_value_t = Cost::Keystrokes)]
+    cost: Cost,
+
+    /// What to do with characters that are not on the layout: fail or skip
+    #[arg(long, value_enum, default_value_t = OnUntypeable::Skip)]
+    untypeable: OnUntypeable,
+
+    /// Type TO in place of FROM when FROM is not on the layout
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_substitute, conflicts_with = "untypeable", allow_hyphen_values = true)]
+    substitute: Vec<(char, char)>,
+
+    /// Read the text from a file, or - for standard input. One new line at the end is left out
+    #[arg(long, value_name = "FILE", conflicts_with = "text")]
+    file: Option<PathBuf>,
+
+    /// Print the instructions as text, or as a JSON object along with any warnings
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Generate instructions for the sample text, Hello
+    #[arg(long, conflicts_with_all = ["text", "file"])]
+    demo: bool,
+
+    /// Write the instructions to a file instead of standard output
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Input text
+    #[arg(required_unless_present_any = ["file", "demo"])]
+    text: Option<String>,
+}
+
+/// Shorten instructions without changing what they type
+#[derive(Parser, Debug)]
+#[command(name = "optimize", author, version, about, long_about = None)]
+struct OptimizeArgs {
+    #[command(flatten)]
+    layout: LayoutArgs,
+
+    /// Reject unknown instructions instead of dropping them
+    #[arg(long)]
+    strict: bool,
+
+    /// Read the instructions from a file, or - for standard input
+    #[arg(long, value_name = "FILE", conflicts_with = "instructions")]
+    file: Option<PathBuf>,
+
+    /// Optimize the sample instructions, which type HELLO
+    #[arg(long, conflicts_with_all = ["instructions", "file"])]
+    demo: bool,
+
+    /// Instructions to optimize, or - to read them from standard input
+    #[arg(required_unless_present_any = ["file", "demo"])]
+    instructions: Option<String>,
+}
+
+/// Run instructions one at a time, showing what each one does
+#[derive(Parser, Debug)]
+#[command(name = "trace", author, version, about, long_about = None)]
+struct TraceArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// Reject unknown instructions instead of ignoring them
+    #[arg(long)]
+    strict: bool,
+
+    /// Print a table, or one JSON object per instruction
+    #[arg(long, value_enum, default_value_t = TraceFormat::Table)]
+    format: TraceFormat,
+
+    /// Read the instructions from a file, or - for standard input
+    #[arg(long, value_name = "FILE", conflicts_with = "instructions")]
+    file: Option<PathBuf>,
+
+    /// Trace the sample instructions, which type HELLO
+    #[arg(long, conflicts_with_all = ["instructions", "file"])]
+    demo: bool,
+
+    /// Instructions to trace, or - to read them from standard input
+    #[arg(required_unless_present_any = ["file", "demo"])]
+    instructions: Option<String>,
+}
+
+/// Count what instructions do on the keyboard
+#[derive(Parser, Debug)]
+#[command(name = "stats", author, version, about, long_about = None)]
+struct StatsArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// Print the counts as text or as a JSON object
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Read the instructions from a file, or - for standard input
+    #[arg(long, value_name = "FILE", conflicts_with = "instructions")]
+    file: Option<PathBuf>,
+
+    /// Count what the sample instructions do, which type HELLO
+    #[arg(long, conflicts_with_all = ["instructions", "file"])]
+    demo: bool,
+
+    /// Instructions to count, or - to read them from standard input
+    #[arg(required_unless_present_any = ["file", "demo"])]
+    instructions: Option<String>,
+}
+
+/// Check instructions for mistakes
+#[derive(Parser, Debug)]
+#[command(name = "validate", author, version, about, long_about = None)]
+struct ValidateArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// Read the instructions from a file, or - for standard input
+    #[arg(long, value_name = "FILE", conflicts_with = "instructions")]
+    file: Option<PathBuf>,
+
+    /// Instructions to check
+    #[arg(required_unless_present = "file")]
+    instructions: Option<String>,
+}
+
+/// Run instructions interactively, a line at a time
+#[derive(Parser, Debug)]
+#[command(name = "repl", author, version, about, long_about = None)]
+struct ReplArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// Reject unknown instructions instead of ignoring them
+    #[arg(long)]
+    strict: bool,
+}
+
+/// Record instructions by playing the keyboard with the arrow keys
+#[cfg(not(target_family = "wasm"))]
+#[derive(Parser, Debug)]
+#[command(name = "tui", author, version, about, long_about = None)]
+struct TuiArgs {
+    #[command(flatten)]
+    keyboard: KeyboardArgs,
+
+    /// File to save the recorded instructions to
+    #[arg(long, default_value = "program.txt")]
+    save: PathBuf,
+}
+
+/// Where the keyboard starts and how it is laid out.
+#[derive(Args, Debug)]
+struct KeyboardArgs {
+    /// X starting position on the keyboard
+    #[arg(short, default_value_t = 4)]
+    x_position: usize,
+
+    /// Y starting position on the keyboard
+    #[arg(short, default_value_t = 2)]
+    y_position: usize,
+
+    #[command(flatten)]
+    layout: LayoutArgs,
+}
+
+impl KeyboardArgs {
+    /// Builds the keyboard, exiting with an error if the layout cannot be loaded or the starting
+    /// position is off it.
+    fn keyboard<O: OutputSink>(&self, output: O) -> Keyboard<O> {
+        let keyboard_layout = self.layout.load();
+        let position = check_position(&keyboard_layout, (self.x_position, self.y_position));
+        Keyboard::builder(keyboard_layout)
+            .position(position)
+            .edge_policy(self.layout.edge_policy)
+            .build_with(output)
+    }
+}
+
+/// The keyboard layout and what happens at its edges.
+#[derive(Args, Debug)]
+struct LayoutArgs {
+    /// What to do when a move runs off the edge: wrap, clamp, error or row-wrap
+    #[arg(long, default_value = "wrap")]
+    edge_policy: EdgePolicy,
+
+    /// Keyboard layout file, as a text grid or a .toml or .json file
+    #[arg(long)]
+    layout: Option<PathBuf>,
+
+    /// Built-in keyboard layout to use instead of QWERTY
+    #[arg(long, conflicts_with = "layout", value_parser = PossibleValuesParser::new(KeyboardLayout::NAMES))]
+    layout_name: Option<String>,
+}
+
+impl LayoutArgs {
+    fn load(&self) -> KeyboardLayout {
+        let path = match &self.layout {
+            Some(path) => path,
+            None => {
+                return KeyboardLayout::named(self.layout_name.as_deref().unwrap_or("qwerty"))
+                    .expect("layout name is checked by clap")
+            }
+        };
+
+        let layout = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| {
+                KeyboardLayout::parse(&source, LayoutFormat::from_path(path))
+                    .map_err(|err| err.to_string())
+            });
+
+        match layout {
+            Ok(layout) => layout,
+            Err(err) => {
+                eprintln!("error: {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    /// The layout name for reports.
+    fn name(&self) -> String {
+        match &self.layout {
+            Some(path) => path.display().to_string(),
+            None => self.layout_name.as_deref().unwrap_or("qwerty").to_string(),
+        }
+    }
+}
+
+fn parse_substitute(s: &str) -> Result<(char, char), String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some(from), Some('='), Some(to), None) => Ok((from, to)),
+        _ => Err(format!(
+            "expected FROM=TO with one character each, found `{}`",
+            s
+        )),
+    }
+}
+
+/// How `run`, `generate` and `stats` print what they did.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// How `trace` prints each instruction.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TraceFormat {
+    Table,
+    Json,
+}
+
+/// What `generate` minimizes.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Cost {
+    Keystrokes,
+    Tokens,
+    Distance,
+}
+
+/// What `generate` does with characters that are not on the layout.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OnUntypeable {
+    Fail,
+    Skip,
+}
+
+fn parse_mode(strict: bool) -> ParseMode {
+    if strict {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    }
+}
+
+/// Instructions given on the command line, or where to stream them from.
+enum Input {
+    Text(String),
+    Reader(Box<dyn BufRead>),
+}
+
+impl Input {
+    /// The positional argument, which is `-` for standard input, or else `--file`, or else the
+    /// `--demo` sample, as clap only allows neither with `--demo`.
+    fn new(argument: Option<String>, file: Option<&Path>, demo: &str) -> Self {
+        match (argument, file) {
+            (Some(argument), _) if argument == "-" => Input::Reader(Box::new(io::stdin().lock())),
+            (Some(argument), _) => Input::Text(argument),
+            (None, Some(path)) => Input::Reader(open_input(path)),
+            (None, None) => Input::Text(demo.to_string()),
+        }
+    }
+
+    /// The instructions one at a time. Instructions on the command line are parsed all at once,
+    /// so if they cannot be, nothing comes before the error.
+    fn steps(self, mode: ParseMode) -> Box<dyn Iterator<Item = Result<Spanned, KeyboardError>>> {
+        match self {
+            Input::Reader(reader) => Box::new(read_instructions(reader, mode)),
+            Input::Text(instructions) => match Program::parse(&instructions, mode) {
+                Ok(program) => Box::new(program.steps().to_vec().into_iter().map(Ok)),
+                Err(err) => Box::new(iter::once(Err(err.into()))),
+            },
+        }
+    }
+
+    /// Reads the whole input.
+    fn read_to_string(self) -> String {
+        match self {
+            Input::Text(text) => text,
+            Input::Reader(mut reader) => {
+                let mut text = String::new();
+                if let Err(err) = reader.read_to_string(&mut text) {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                }
+                text
+            }
+        }
+    }
+}
+
+/// Opens a file to read, or standard input for `-`.
+fn open_input(path: &Path) -> Box<dyn BufRead> {
+    if path == Path::new("-") {
+        return Box::new(io::stdin().lock());
+    }
+
+    match File::open(path) {
+        Ok(file) => Box::new(BufReader::new(file)),
+        Err(err) => {
+            eprintln!("error: {}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Creates a file to write to, or standard output if there is none.
+fn create_output(path: Option<&Path>) -> Box<dyn Write> {
+    let path = match path {
+        Some(path) => path,
+        None => return Box::new(io::stdout().lock()),
+    };
+
+    match File::create(path) {
+        Ok(file) => Box::new(BufWriter::new(file)),
+        Err(err) => {
+            eprintln!("error: {}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
+
+fn check_position(layout: &KeyboardLayout, position: Position) -> Position {
+    let (width, height) = layout.size();
+    if position.0 >= width || position.1 >= height {
+        eprintln!(
+            "error: starting position ({}, {}) is off the {}x{} layout",
+            position.0, position.1, width, height
+        );
+        process::exit(1);
+    }
+    position
+}
+
+/// A key as a quoted character, or `-` for no key.
+fn key_cell(key: Option<char>) -> String {
+    key.map_or_else(|| "-".to_string(), |key| format!("{:?}", key))
+}
+
+fn print_event(event: &Event, format: TraceFormat) {
+    match format {
+        TraceFormat::Json => {
+            let json = serde_json::json!({
+                "index": event.index,
+                "instruction": event.instruction.to_string(),
+                "before": [event.before.0, event.before.1],
+                "after": [event.after.0, event.after.1],
+                "key": event.key.map(String::from),
+                "emitted": event.emitted.map(String::from),
+                "wrapped": event.wrapped,
+                "layer": event.layers.active(),
+            });
+            println!("{}", json);
+        }
+        TraceFormat::Table => println!(
+            "{:>5}  {:<11}  {:<8}  {:<8}  {:<5}  {:<7}  {}",
+            event.index,
+            event.instruction.to_string(),
+            format!("({}, {})", event.before.0, event.before.1),
+            format!("({}, {})", event.after.0, event.after.1),
+            key_cell(event.key),
+            key_cell(event.emitted),
+            if event.wrapped { "yes" } else { "no" }
+        ),
+    }
+}
+
+fn print_stats(stats: &Stats) {
+    println!("moves       {}", stats.moves);
+    println!("distance    {}", stats.distance);
+    println!("presses     {}", stats.presses);
+    println!("wraps       {}", stats.wraps);
+    println!("selections  {}", stats.selections);
+    println!("unknown     {}", stats.unknown);
+    println!();
+    println!("key    selected  visited");
+
+    let mut keys: Vec<char> = stats
+        .selected
+        .keys()
+        .chain(stats.visited.keys())
+        .copied()
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+    for key in keys {
+        println!(
+            "{:<5}  {:>8}  {:>7}",
+            key_cell(Some(key)),
+            stats.selected.get(&key).unwrap_or(&0),
+            stats.visited.get(&key).unwrap_or(&0)
+        );
+    }
+}
+
+/// What `run` and `generate` print with `--format json`. The fields are documented in the
+/// README and only ever added to.
+#[derive(Serialize)]
+struct Report {
+    /// The text typed by `run`, or the instructions from `generate`.
+    output: String,
+    /// Where the cursor is after the instructions, as `[x, y]`.
+    position: Position,
+    /// The built-in layout name, or the layout file.
+    layout: String,
+    /// How many instructions ran, or were generated.
+    instructions: usize,
+    diagnostics: Vec<Diagnostic>,
+    /// What stopped the instructions, if anything did.
+    error: Option<String>,
+}
+
+/// Something worth knowing about the input that did not stop it.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum Diagnostic {
+    /// An unknown instruction, which was ignored.
+    UnknownInstruction { index: usize, token: String },
+    /// A character of the text that is not on the layout, which was skipped or substituted.
+    Untypeable {
+        index: usize,
+        character: char,
+        substitute: Option<char>,
+    },
+}
+
+/// Writes a report as a single line of JSON, exiting with an error status if it has an error.
+fn write_report(path: Option<&Path>, report: &Report) {
+    let mut output = create_output(path);
+    let written = serde_json::to_writer(&mut output, report)
+        .map_err(io::Error::from)
+        .and_then(|_| writeln!(output))
+        .and_then(|_| output.flush());
+
+    if let Err(err) = written {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+    if report.error.is_some() {
+        process::exit(1);
+    }
+}
+
+/// The sample instructions used by `--demo`, which type HELLO.
+const DEMO_INSTRUCTIONS: &str = "R,S,U,L:3,S,D,R:6,S,S,U,S";
+
+/// The text `generate --demo` generates instructions for.
+const DEMO_TEXT: &str = "Hello";
+
+/// Moves the terminal cursor to the top left corner and clears the screen.
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+/// Runs the steps one at a time, redrawing the keyboard after each one.
+fn animate(
+    keyboard: &mut Keyboard,
+    steps: impl Iterator<Item = Result<Spanned, KeyboardError>>,
+    delay: Duration,
+) -> Result<(), KeyboardError> {
+    let draw = |keyboard: &Keyboard| -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "{}{}", CLEAR_SCREEN, keyboard.render())?;
+        stdout.flush()
+    };
+
+    draw(keyboard)?;
+    for (index, step) in steps.enumerate() {
+        thread::sleep(delay);
+        keyboard.execute(index, &step?.instruction)?;
+        draw(keyboard)?;
+    }
+    println!();
+
+    Ok(())
+}
+
+fn main() {
+    let args = KeyboardMadness::parse();
+
+    match args.command {
+        Command::Run(run_args) if run_args.animate => {
+            let mut keyboard = run_args.keyboard.keyboard(vec![]);
+            let delay = Duration::from_millis(run_args.delay);
+
+            let steps = Input::new(
+                run_args.instructions,
+                run_args.file.as_deref(),
+                DEMO_INSTRUCTIONS,
+            )
+            .steps(parse_mode(run_args.strict));
+
+            if let Err(err) = animate(&mut keyboard, steps, delay) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        Command::Run(run_args) if matches!(run_args.format, Format::Json) => {
+            let mut keyboard = run_args.keyboard.keyboard(vec![]);
+            let steps = Input::new(
+                run_args.instructions,
+                run_args.file.as_deref(),
+                DEMO_INSTRUCTIONS,
+            )
+            .steps(parse_mode(run_args.strict));
+
+            let mut instructions = 0;
+            let mut diagnostics = vec![];
+            let result = steps.enumerate().try_for_each(|(index, step)| {
+                let instruction = step?.instruction;
+                keyboard.execute(index, &instruction)?;
+                instructions += 1;
+                if let Instruction::Unknown(token) = instruction {
+                    diagnostics.push(Diagnostic::UnknownInstruction { index, token });
+                }
+                Ok::<_, KeyboardError>(())
+            });
+
+            let report = Report {
+                output: keyboard.to_string(),
+                position: keyboard.position,
+                layout: run_args.keyboard.layout.name(),
+                instructions,
+                diagnostics,
+                error: result.as_ref().err().map(ToString::to_string),
+            };
+            write_report(run_args.output.as_deref(), &report);
+        }
+        Command::Run(run_args) => {
+            // Keys are written as they are selected, so long programs never build up their output.
+            let mut keyboard = run_args
+                .keyboard
+                .keyboard(WriteSink::new(create_output(run_args.output.as_deref())));
+            let input = Input::new(
+                run_args.instructions,
+                run_args.file.as_deref(),
+                DEMO_INSTRUCTIONS,
+            );
+            let result = match (input, run_args.strict) {
+                (Input::Reader(reader), strict) => keyboard.run_reader(reader, parse_mode(strict)),
+                (Input::Text(instructions), true) => keyboard.run_strict(&instructions),
+                (Input::Text(instructions), false) => keyboard.run(&instructions),
+            };
+            // End the line of output, unless the instructions stopped before typing anything.
+            // Streamed instructions may have typed some keys before an error, and those still
+            // need the line ending so that the error starts on its own line.
+            let typed = result.is_ok() || keyboard.selected_keys.written() > 0;
+            let written = keyboard.selected_keys.finish().and_then(|mut output| {
+                if typed {
+                    writeln!(output)?;
+                }
+                output.flush()
+            });
+
+            if let Err(err) = result {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+            if let Err(err) = written {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        Command::Generate(generate_args) => {
+            let mut keyboard = generate_args.keyboard.keyboard(vec![]);
+
+            let options = GenerateOptions {
+                cost_model: match generate_args.cost {
+                    Cost::Keystrokes => &Keystrokes,
+                    Cost::Tokens => &TokenCount,
+                    Cost::Distance => &Manhattan,
+                },
+                untypeable: if !generate_args.substitute.is_empty() {
+                    UntypeablePolicy::Substitute(generate_args.substitute.into_iter().collect())
+                } else {
+                    match generate_args.untypeable {
+                        OnUntypeable::Fail => UntypeablePolicy::Fail,
+                        OnUntypeable::Skip => UntypeablePolicy::Skip,
+                    }
+                },
+                ..GenerateOptions::default()
+            };
+
+            let input = Input::new(generate_args.text, generate_args.file.as_deref(), DEMO_TEXT);
+            let read = matches!(input, Input::Reader(_));
+            let mut text = input.read_to_string();
+            // Files and piped input almost always end in a new line that is not meant to be typed.
+            if read && text.ends_with('\n') {
+                text.pop();
+                if text.ends_with('\r') {
+                    text.pop();
+                }
+            }
+
+            let result = keyboard.generate(&text, &options);
+            match generate_args.format {
+                Format::Json => {
+                    let layout = generate_args.keyboard.layout.name();
+                    let report = match result {
+                        Ok(generated) => Report {
+                            output: generated.program.to_string(),
+                            position: generated.position,
+                            layout,
+                            instructions: generated.program.len(),
+                            diagnostics: generated
+                                .untypeable
+                                .iter()
+                                .map(|untypeable| Diagnostic::Untypeable {
+                                    index: untypeable.index,
+                                    character: untypeable.key,
+                                    substitute: untypeable.substitute,
+                                })
+                                .collect(),
+                            error: None,
+                        },
+                        Err(err) => Report {
+                            output: String::new(),
+                            position: keyboard.position,
+                            layout,
+                            instructions: 0,
+                            diagnostics: vec![],
+                            error: Some(err.to_string()),
+                        },
+                    };
+                    write_report(generate_args.output.as_deref(), &report);
+                }
+                Format::Text => {
+                    let generated = match result {
+                        Ok(generated) => generated,
+                        Err(err) => {
+                            eprintln!("error: {}", err);
+                            process::exit(1);
+                        }
+                    };
+                    for untypeable in &generated.untypeable {
+                        match untypeable.substitute {
+                            Some(substitute) => {
+                                eprintln!("warning: {}, typed {:?} instead", untypeable, substitute)
+                            }
+                            None => eprintln!("warning: {}, skipped", untypeable),
+                        }
+                    }
+                    let mut output = create_output(generate_args.output.as_deref());
+                    if let Err(err) =
+                        writeln!(output, "{}", generated.program).and_then(|_| output.flush())
+                    {
+                        eprintln!("error: {}", err);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        Command::Optimize(optimize_args) => {
+            let keyboard_layout = optimize_args.layout.load();
+            let instructions = Input::new(
+                optimize_args.instructions,
+                optimize_args.file.as_deref(),
+                DEMO_INSTRUCTIONS,
+            )
+            .read_to_string();
+            let program = match Program::parse(&instructions, parse_mode(optimize_args.strict)) {
+                Ok(program) => program,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                }
+            };
+
+            println!(
+                "{}",
+                program.optimize(optimize_args.layout.edge_policy, keyboard_layout.size())
+            );
+        }
+        Command::Trace(trace_args) => {
+            let mut keyboard = trace_args.keyboard.keyboard(NullSink);
+            let instructions = Input::new(
+                trace_args.instructions,
+                trace_args.file.as_deref(),
+                DEMO_INSTRUCTIONS,
+            )
+            .read_to_string();
+            let program = match Program::parse(&instructions, parse_mode(trace_args.strict)) {
+                Ok(program) => program,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                }
+            };
+
+            if let TraceFormat::Table = trace_args.format {
+                println!(
+                    "{:>5}  {:<11}  {:<8}  {:<8}  {:<5}  {:<7}  wrapped",
+                    "index", "instruction", "before", "after", "key", "emitted"
+                );
+            }
+            for event in keyboard.trace(&program) {
+                match event {
+                    Ok(event) => print_event(&event, trace_args.format),
+                    Err(err) => {
+                        eprintln!("error: {}", err);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        Command::Validate(validate_args) => {
+            let keyboard = validate_args.keyboard.keyboard(vec![]);
+            // clap requires the instructions or a file, so there is no demo to fall back on.
+            let instructions = Input::new(
+                validate_args.instructions,
+                validate_args.file.as_deref(),
+                "",
+            )
+            .read_to_string();
+
+            let findings = keyboard.validate(&instructions);
+            for finding in &findings {
+                println!("{}", finding);
+            }
+
+            let errors = findings
+                .iter()
+                .filter(|finding| finding.severity() == Severity::Error)
+                .count();
+            let warnings = findings.len() - errors;
+            println!(
+                "{} error{}, {} warning{}",
+                errors,
+                if errors == 1 { "" } else { "s" },
+                warnings,
+                if warnings == 1 { "" } else { "s" }
+            );
+            if errors > 0 {
+                process::exit(1);
+            }
+        }
+        Command::Repl(repl_args) => {
+            let keyboard = repl_args.keyboard.keyboard(vec![]);
+            let mode = parse_mode(repl_args.strict);
+
+            if let Err(err) = Repl::new(keyboard, mode).run(io::stdin().lock(), io::stdout()) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        #[cfg(not(target_family = "wasm"))]
+        Command::Tui(tui_args) => {
+            let keyboard = tui_args.keyboard.keyboard(vec![]);
+
+            if let Err(err) = Tui::new(keyboard, tui_args.save).run() {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        Command::Stats(stats_args) => {
+            let instructions = Input::new(
+                stats_args.instructions,
+                stats_args.file.as_deref(),
+                DEMO_INSTRUCTIONS,
+            )
+            .read_to_string();
+            let program = match Program::parse(&instructions, ParseMode::Lenient) {
+                Ok(program) => program,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                }
+            };
+            let mut keyboard = stats_args.keyboard.keyboard(NullSink);
+
+            let stats = match keyboard.stats(&program) {
+                Ok(stats) => stats,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                }
+            };
+            match stats_args.format {
+                Format::Text => print_stats(&stats),
+                Format::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&stats).expect("stats serialize")
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use keyboard_madness::{
+    GenerateOptions, Keyboard, KeyboardLayout, LayoutFormat, ParseMode, Position, Program,
+};
+
+const HELP: &str = "\
+Enter instructions to run them, or one of:
+  :reset         clear the output and go back to the starting position
+  :pos X Y       move the cursor to (X, Y)
+  :layout NAME   switch to a built-in layout or a layout file
+  :undo          undo the last line
+  :gen TEXT      print the instructions that type TEXT from here
+  :history       list the lines run so far
+  :help          print this message
+  :quit          leave";
+
+/// An interactive session on one keyboard, keeping enough of its past to undo each line.
+pub struct Repl {
+    keyboard: Keyboard,
+    start: Position,
+    mode: ParseMode,
+    /// The instruction lines run so far.
+    history: Vec<String>,
+    /// The keyboard, the starting position and the length of the history before each line that
+    /// changed them.
+    undo: Vec<(Keyboard, Position, usize)>,
+}
+
+impl Repl {
+    pub fn new(keyboard: Keyboard, mode: ParseMode) -> Self {
+        Repl {
+            start: keyboard.position,
+            keyboard,
+            mode,
+            history: vec![],
+            undo: vec![],
+        }
+    }
+
+    /// Reads lines from `input` until it ends or `:quit`, writing a prompt before each line and
+    /// what it did after it.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line == ":quit" {
+                return Ok(());
+            }
+
+            match self.eval(line) {
+                Ok(message) if message.is_empty() => {}
+                Ok(message) => writeln!(output, "{}", message)?,
+                Err(message) => writeln!(output, "error: {}", message)?,
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    /// Runs one line, returning what to print, or what went wrong if nothing changed.
+    fn eval(&mut self, line: &str) -> Result<String, String> {
+        let (command, argument) = match line.strip_prefix(':') {
+            Some(command) => command
+                .split_once(char::is_whitespace)
+                .map_or((command, ""), |(command, argument)| {
+                    (command, argument.trim())
+                }),
+            None if line.is_empty() => return Ok(String::new()),
+            None => return self.instructions(line),
+        };
+
+        match command {
+            "reset" => {
+                self.save();
+                self.keyboard.reset(self.start);
+                Ok(self.state())
+            }
+            "pos" => {
+                let position = parse_position(argument)?;
+                let (width, height) = self.keyboard.keyboard_layout.size();
+                if position.0 >= width || position.1 >= height {
+                    return Err(format!(
+                        "({}, {}) is off the {}x{} layout",
+                        position.0, position.1, width, height
+                    ));
+                }
+                self.save();
+                self.keyboard.position = position;
+                Ok(self.state())
+            }
+            "layout" => {
+                let layout = load_layout(argument)?;
+                self.save();
+                let (width, height) = layout.size();
+                if self.keyboard.position.0 >= width || self.keyboard.position.1 >= height {
+                    self.keyboard.position = (0, 0);
+                }
+                if self.start.0 >= width || self.start.1 >= height {
+                    self.start = (0, 0);
+                }
+                self.keyboard.keyboard_layout = layout;
+                Ok(self.state())
+            }
+            "undo" => {
+                let (keyboard, start, history) = self.undo.pop().ok_or("nothing to undo")?;
+                self.keyboard = keyboard;
+                self.start = start;
+                self.history.truncate(history);
+                Ok(self.state())
+            }
+            "gen" => {
+                let generated = self
+                    .keyboard
+                    .generate(argument, &GenerateOptions::default())
+                    .map_err(|err| err.to_string())?;
+                let mut lines: Vec<String> = generated
+                    .untypeable
+                    .iter()
+                    .map(|untypeable| format!("warning: {}, skipped", untypeable))
+                    .collect();
+                lines.push(generated.program.to_string());
+                Ok(lines.join("\n"))
+            }
+            "history" => Ok(self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(index, line)| format!("{:>4}  {}", index + 1, line))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "help" => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command `:{}`, try :help", command)),
+        }
+    }
+
+    /// Runs a line of instructions. A line that fails part way through is undone.
+    fn instructions(&mut self, line: &str) -> Result<String, String> {
+        let program = Program::parse(line, self.mode).map_err(|err| err.to_string())?;
+        let before = self.keyboard.clone();
+        if let Err(err) = self.keyboard.run_program(&program) {
+            self.keyboard = before;
+            return Err(err.to_string());
+        }
+
+        self.undo.push((before, self.start, self.history.len()));
+        self.history.push(line.to_string());
+        Ok(self.state())
+    }
+
+    fn save(&mut self) {
+        self.undo
+            .push((self.keyboard.clone(), self.start, self.history.len()));
+    }
+
+    /// The cursor position and the keys selected so far.
+    fn state(&self) -> String {
+        let (x, y) = self.keyboard.position;
+        format!("({}, {}) {:?}", x, y, self.keyboard.to_string())
+    }
+}
+
+fn parse_position(argument: &str) -> Result<Position, String> {
+    let mut numbers = argument.split_whitespace().map(str::parse::<usize>);
+    match (numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok((x, y)),
+        _ => Err(format!("expected `:pos X Y`, found `:pos {}`", argument)),
+    }
+}
+
+/// Loads a built-in layout by name, or else a layout file.
+fn load_layout(argument: &str) -> Result<KeyboardLayout, String> {
+    if let Some(layout) = KeyboardLayout::named(argument) {
+        return Ok(layout);
+    }
+
+    let path = Path::new(argument);
+    fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| {
+            KeyboardLayout::parse(&source, LayoutFormat::from_path(path))
+                .map_err(|err| err.to_string())
+        })
+        .map_err(|err| format!("{}: {}", argument, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use keyboard_madness::{Keyboard, ParseMode, KEYS};
+
+    use super::Repl;
+
+    /// Runs `lines` through a new session from `(4, 2)`, returning what it wrote.
+    fn session(lines: &str) -> String {
+        let keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+        let mut output = vec![];
+        Repl::new(keyboard, ParseMode::Lenient)
+            .run(lines.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_should_run_and_undo_lines() {
+        assert_eq!(
+            session("R,S\nU,S\n:undo\n:undo\n:undo\n"),
+            "> (5, 2) \"H\"\n\
+             > (5, 1) \"HY\"\n\
+             > (5, 2) \"H\"\n\
+             > (4, 2) \"\"\n\
+             > error: nothing to undo\n\
+             > \n"
+        );
+    }
+
+    #[test]
+    fn test_should_reset_to_the_start() {
+        assert_eq!(
+            session("R,S\n:reset\n:undo\n:quit\nS\n"),
+            "> (5, 2) \"H\"\n\
+             > (4, 2) \"\"\n\
+             > (5, 2) \"H\"\n\
+             > "
+        );
+    }
+
+    #[test]
+    fn test_should_undo_switching_layouts() {
+        assert_eq!(
+            session(":layout phone\nR,S\n:reset\n:undo\n:undo\n:undo\n:reset\n"),
+            "> (0, 0) \"\"\n\
+             > (1, 0) \"2\"\n\
+             > (0, 0) \"\"\n\
+             > (1, 0) \"2\"\n\
+             > (0, 0) \"\"\n\
+             > (4, 2) \"\"\n\
+             > (4, 2) \"\"\n\
+             > \n"
+        );
+    }
+
+    #[test]
+    fn test_should_generate_without_running() {
+        assert_eq!(
+            session(":gen HI!\nS\n"),
+            "> warning: character 2 '!' is not on the layout, skipped\n\
+             R,S,R:2,U,S\n\
+             > (4, 2) \"G\"\n\
+             > \n"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use keyboard_madness::{Instruction, Keyboard, KeyboardLayout, Position, Program};
+
+const HELP: &str = "arrows move  enter select  space _  n N  tab layer  ^ shift  m lock  \
+    backspace undo  w save  q quit";
+
+/// Columns of the program pane.
+const PANE_WIDTH: usize = 40;
+
+/// Records a program by playing the keyboard: each key press runs an instruction and adds it
+/// to the program.
+pub struct Tui {
+    keyboard: Keyboard,
+    start: Position,
+    /// The instructions played so far.
+    program: Program,
+    /// The layer `^` and `m` shift to and lock.
+    layer: usize,
+    /// Where `w` saves the program.
+    save: PathBuf,
+    /// The result of the last key press that did not just run an instruction.
+    status: String,
+}
+
+impl Tui {
+    pub fn new(keyboard: Keyboard, save: PathBuf) -> Self {
+        let layers = keyboard.keyboard_layout.layer_count();
+        Tui {
+            start: keyboard.position,
+            layer: KeyboardLayout::SHIFT.min(layers - 1),
+            keyboard,
+            program: Program::new(),
+            save,
+            status: String::new(),
+        }
+    }
+
+    /// Takes over the terminal until `q` or escape is pressed, putting it back afterwards even
+    /// when drawing fails.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let result = self.event_loop(&mut stdout);
+
+        queue!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+        stdout.flush()?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn event_loop(&mut self, stdout: &mut impl Write) -> io::Result<()> {
+        loop {
+            self.draw(stdout)?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            if !self.press(key) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handles a key press, returning whether to keep going.
+    fn press(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('w') => self.save(),
+            KeyCode::Backspace => self.undo(),
+            KeyCode::Tab => {
+                self.layer = (self.layer + 1) % self.keyboard.keyboard_layout.layer_count()
+            }
+            _ => {
+                if let Some(instruction) = self.instruction(key) {
+                    self.play(instruction);
+                }
+            }
+        }
+        true
+    }
+
+    /// Runs an instruction and records it, unless it fails.
+    fn play(&mut self, instruction: Instruction) {
+        match self.keyboard.execute(self.program.len(), &instruction) {
+            Ok(_) => {
+                self.program.push(instruction);
+                self.status.clear();
+            }
+            Err(err) => self.status = format!("error: {}", err),
+        }
+    }
+
+    /// Drops the last instruction and plays the rest again from the start.
+    fn undo(&mut self) {
+        let mut instructions: Vec<Instruction> = self.program.clone().into();
+        if instructions.pop().is_none() {
+            self.status = "nothing to undo".to_string();
+            return;
+        }
+
+        self.program = instructions.into();
+        self.keyboard.reset(self.start);
+        self.status = match self.keyboard.run_program(&self.program) {
+            Ok(()) => String::new(),
+            Err(err) => format!("error: {}", err),
+        };
+    }
+
+    /// The instruction a key plays, if any.
+    fn instruction(&self, key: KeyEvent) -> Option<Instruction> {
+        Some(match key.code {
+            KeyCode::Left => Instruction::Left(1),
+            KeyCode::Up => Instruction::Up(1),
+            KeyCode::Right => Instruction::Right(1),
+            KeyCode::Down => Instruction::Down(1),
+            KeyCode::Enter => Instruction::Select,
+            KeyCode::Char(' ') => Instruction::Space,
+            KeyCode::Char('n') => Instruction::NewLine,
+            KeyCode::Char('^') => Instruction::Shift(self.layer),
+            KeyCode::Char('m') => Instruction::Lock(self.layer),
+            _ => return None,
+        })
+    }
+
+    fn save(&mut self) {
+        self.status = match fs::write(&self.save, format!("{}\n", self.recorded())) {
+            Ok(()) => format!("saved to {}", self.save.display()),
+            Err(err) => format!("error: {}: {}", self.save.display(), err),
+        };
+    }
+
+    /// The program played so far, with its moves merged.
+    fn recorded(&self) -> Program {
+        self.program.optimize(
+            self.keyboard.edge_policy,
+            self.keyboard.keyboard_layout.size(),
+        )
+    }
+
+    fn draw(&self, stdout: &mut impl Write) -> io::Result<()> {
+        queue!(stdout, terminal::Clear(ClearType::All))?;
+
+        let (width, _) = self.keyboard.keyboard_layout.size();
+        let pane = (width * 3 + 4) as u16;
+        let mut row = 0;
+        for line in self.keyboard.render().split('\n') {
+            queue!(stdout, cursor::MoveTo(0, row), Print(line))?;
+            row += 1;
+        }
+
+        let program = self.recorded().to_string();
+        let lines = program
+            .as_bytes()
+            .chunks(PANE_WIDTH)
+            .map(String::from_utf8_lossy);
+        queue!(stdout, cursor::MoveTo(pane, 0), Print("Program"))?;
+        for (offset, line) in lines.enumerate() {
+            queue!(stdout, cursor::MoveTo(pane, offset as u16 + 1), Print(line))?;
+        }
+
+        queue!(
+            stdout,
+            cursor::MoveTo(0, row + 1),
+            Print(&self.status),
+            cursor::MoveTo(0, row + 2),
+            Print(format!(
+                "layer {} of {}",
+                self.layer,
+                self.keyboard.keyboard_layout.layer_count()
+            )),
+            cursor::MoveTo(0, row + 3),
+            Print(HELP)
+        )?;
+        stdout.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use keyboard_madness::{EdgePolicy, Keyboard, KeyboardLayout, Position};
+
+    use super::Tui;
+
+    /// Presses `keys` on a new recording, returning it afterwards.
+    fn record(keyboard: Keyboard, keys: &[KeyCode]) -> Tui {
+        let mut tui = Tui::new(keyboard, "program.txt".into());
+        for &key in keys {
+            assert!(tui.press(KeyEvent::new(key, KeyModifiers::NONE)));
+        }
+        tui
+    }
+
+    fn qwerty(position: Position, edge_policy: EdgePolicy) -> Keyboard {
+        Keyboard::builder(KeyboardLayout::named("qwerty").unwrap())
+            .position(position)
+            .edge_policy(edge_policy)
+            .build()
+    }
+
+    #[test]
+    fn test_should_record_the_keys_played() {
+        let tui = record(
+            qwerty((4, 2), EdgePolicy::Wrap),
+            &[
+                KeyCode::Right,
+                KeyCode::Enter,
+                KeyCode::Up,
+                KeyCode::Left,
+                KeyCode::Left,
+                KeyCode::Left,
+                KeyCode::Char('^'),
+                KeyCode::Enter,
+                KeyCode::Char(' '),
+                KeyCode::Char('x'),
+            ],
+        );
+
+        assert_eq!(tui.recorded().to_string(), "R,S,^,L:3,U,S,_");
+        assert_eq!(tui.keyboard.to_string(), "He ");
+    }
+
+    #[test]
+    fn test_should_undo_by_playing_again_from_the_start() {
+        let mut tui = record(
+            qwerty((4, 2), EdgePolicy::Wrap),
+            &[
+                KeyCode::Right,
+                KeyCode::Enter,
+                KeyCode::Char('m'),
+                KeyCode::Up,
+            ],
+        );
+
+        assert!(tui.press(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE)));
+        assert_eq!(tui.recorded().to_string(), "R,S,M");
+        assert_eq!(tui.keyboard.position, (5, 2));
+        assert_eq!(tui.keyboard.layers.locked, KeyboardLayout::SHIFT);
+        assert_eq!(tui.keyboard.to_string(), "H");
+
+        for _ in 0..4 {
+            tui.press(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        }
+        assert_eq!(tui.recorded().to_string(), "");
+        assert_eq!(tui.keyboard.position, (4, 2));
+        assert_eq!(tui.status, "nothing to undo");
+    }
+
+    #[test]
+    fn test_should_not_record_moves_off_the_edge() {
+        let mut tui = record(qwerty((9, 2), EdgePolicy::Error), &[KeyCode::Right]);
+        assert_eq!(tui.recorded().to_string(), "");
+        assert_eq!(
+            tui.status,
+            "error: instruction 0 moves off the keyboard from (9, 2)"
+        );
+
+        tui.press(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(tui.recorded().to_string(), "S");
+        assert_eq!(tui.keyboard.to_string(), ";");
+        assert_eq!(tui.status, "");
+    }
+
+    #[test]
+    fn test_should_cycle_through_every_layer() {
+        let mut tui = record(qwerty((4, 2), EdgePolicy::Wrap), &[]);
+        assert_eq!(tui.layer, KeyboardLayout::SHIFT);
+
+        let mut layers = vec![];
+        for _ in 0..3 {
+            tui.press(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+            layers.push(tui.layer);
+        }
+        assert_eq!(layers, vec![2, 0, 1]);
+
+        tui.press(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        tui.press(KeyEvent::new(KeyCode::Char('^'), KeyModifiers::NONE));
+        tui.press(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::NONE));
+        assert_eq!(tui.recorded().to_string(), "^:2,M:2");
+    }
+
+    #[test]
+    fn test_should_quit_on_q_escape_and_control_c() {
+        let mut tui = record(qwerty((4, 2), EdgePolicy::Wrap), &[]);
+
+        assert!(!tui.press(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert!(!tui.press(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
+        assert!(!tui.press(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+    }
+}
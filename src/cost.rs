@@ -0,0 +1,92 @@
+use crate::{Instruction, Program};
+
+/// How much running an instruction costs on a device, for [`Keyboard::generate_instructions_with`]
+/// to find the cheapest program.
+///
+/// The cost of a move must not go down as its count goes up, so that the cheapest way to reach a
+/// key never needs to move further than across the keyboard.
+///
+/// Any `Fn(&Instruction) -> usize` is a cost model too, so weighting vertical moves higher is a
+/// closure away.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{CostModel, Instruction, Keystrokes, Program, TokenCount};
+///
+/// let program: Program = "R:3,S,^,U,S".parse().unwrap();
+///
+/// assert_eq!(program.cost(&TokenCount), 5);
+/// assert_eq!(program.cost(&Keystrokes), 7);
+///
+/// let vertical = |instruction: &Instruction| match *instruction {
+///     Instruction::Up(count) | Instruction::Down(count) => 3 * count,
+///     _ => Keystrokes.cost(instruction),
+/// };
+/// assert_eq!(program.cost(&vertical), 9);
+/// ```
+///
+/// [`Keyboard::generate_instructions_with`]: crate::Keyboard::generate_instructions_with
+pub trait CostModel {
+    /// The cost of running `instruction` once.
+    fn cost(&self, instruction: &Instruction) -> usize;
+}
+
+impl<F: Fn(&Instruction) -> usize> CostModel for F {
+    fn cost(&self, instruction: &Instruction) -> usize {
+        self(instruction)
+    }
+}
+
+/// Every instruction costs 1, for devices that charge per token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TokenCount;
+
+impl CostModel for TokenCount {
+    fn cost(&self, _: &Instruction) -> usize {
+        1
+    }
+}
+
+/// Every button press costs 1, so a move costs its count and anything else costs 1. This is the
+/// default for [`Keyboard::generate_instructions`](crate::Keyboard::generate_instructions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Keystrokes;
+
+impl CostModel for Keystrokes {
+    fn cost(&self, instruction: &Instruction) -> usize {
+        match *instruction {
+            Instruction::Left(count)
+            | Instruction::Up(count)
+            | Instruction::Right(count)
+            | Instruction::Down(count) => count,
+            _ => 1,
+        }
+    }
+}
+
+/// Only the distance the cursor travels costs anything, so a move costs its count and anything
+/// else is free.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Manhattan;
+
+impl CostModel for Manhattan {
+    fn cost(&self, instruction: &Instruction) -> usize {
+        match *instruction {
+            Instruction::Left(count)
+            | Instruction::Up(count)
+            | Instruction::Right(count)
+            | Instruction::Down(count) => count,
+            _ => 0,
+        }
+    }
+}
+
+impl Program {
+    /// The total cost of running every instruction once under `cost_model`.
+    pub fn cost(&self, cost_model: &dyn CostModel) -> usize {
+        self.instructions()
+            .map(|instruction| cost_model.cost(instruction))
+            .sum()
+    }
+}
@@ -0,0 +1,663 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    fmt,
+};
+
+use crate::{
+    CostModel, Direction, EdgePolicy, Instruction, KeyboardLayout, Keystrokes, LayerState,
+    Position, Program,
+};
+
+/// How [`Keyboard::generate`](crate::Keyboard::generate) generates instructions.
+#[derive(Clone)]
+pub struct GenerateOptions<'a> {
+    /// What the generated instructions should cost as little as possible of.
+    pub cost_model: &'a dyn CostModel,
+    /// What to do with characters that are not on the layout.
+    pub untypeable: UntypeablePolicy,
+    /// Whether to leave the keyboard where the generated instructions end, so that instructions
+    /// generated next carry on from there.
+    pub advance: bool,
+}
+
+impl Default for GenerateOptions<'_> {
+    fn default() -> Self {
+        GenerateOptions {
+            cost_model: &Keystrokes,
+            untypeable: UntypeablePolicy::default(),
+            advance: false,
+        }
+    }
+}
+
+/// What to do with a character in the text that is not on any layer of the layout.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum UntypeablePolicy {
+    /// Stop with [`KeyboardError::Untypeable`](crate::KeyboardError::Untypeable).
+    Fail,
+    /// Leave the character out.
+    #[default]
+    Skip,
+    /// Type the character it maps to instead, such as `.` for `!` or a space for a tab. Characters
+    /// that map to nothing or to another untypeable character are left out.
+    Substitute(HashMap<char, char>),
+}
+
+/// A character in the text that is not on the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Untypeable {
+    /// Which character of the text it is, counting from 0.
+    pub index: usize,
+    pub key: char,
+    /// What was typed instead, if anything.
+    pub substitute: Option<char>,
+}
+
+impl fmt::Display for Untypeable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "character {} {:?} is not on the layout",
+            self.index, self.key
+        )
+    }
+}
+
+/// The instructions generated for a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generated {
+    pub program: Program,
+    /// The characters that could not be typed as they are, in the order they appear.
+    pub untypeable: Vec<Untypeable>,
+    /// Where the cursor is after running the program.
+    pub position: Position,
+    /// The layers after running the program.
+    pub layers: LayerState,
+}
+
+/// Whether `ch` can be typed on `layout`.
+pub(crate) fn typeable(layout: &KeyboardLayout, ch: char) -> bool {
+    ch == ' ' || ch == '\n' || layout.find(ch).is_some()
+}
+
+/// What a cost model charges, then the number of instructions, so that the fewer instructions
+/// win between programs that cost the same.
+type Cost = (usize, usize);
+
+/// The cheapest instructions from one node to every other, found with Dijkstra's algorithm.
+struct Paths {
+    costs: Vec<Option<Cost>>,
+    previous: Vec<Option<(usize, Instruction)>>,
+}
+
+impl Paths {
+    /// Finds the cheapest paths from `start` over `len` nodes, where `edges` lists the node each
+    /// instruction leads to from a node.
+    fn from(
+        start: usize,
+        len: usize,
+        cost_model: &dyn CostModel,
+        edges: impl Fn(usize) -> Vec<(usize, Instruction)>,
+    ) -> Self {
+        let mut paths = Paths {
+            costs: vec![None; len],
+            previous: vec![None; len],
+        };
+        let mut queue = BinaryHeap::from([Reverse(((0, 0), start))]);
+        paths.costs[start] = Some((0, 0));
+
+        while let Some(Reverse((cost, node))) = queue.pop() {
+            if paths.costs[node].is_some_and(|best| best < cost) {
+                continue;
+            }
+
+            for (next, instruction) in edges(node) {
+                let next_cost = add(cost, (cost_model.cost(&instruction), 1));
+                if paths.costs[next].is_none_or(|best| next_cost < best) {
+                    paths.costs[next] = Some(next_cost);
+                    paths.previous[next] = Some((node, instruction));
+                    queue.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+
+        paths
+    }
+
+    fn to(&self, mut node: usize) -> Option<(Cost, Vec<Instruction>)> {
+        let cost = self.costs[node]?;
+        let mut instructions = vec![];
+        while let Some((previous, instruction)) = &self.previous[node] {
+            instructions.push(instruction.clone());
+            node = *previous;
+        }
+        instructions.reverse();
+
+        Some((cost, instructions))
+    }
+}
+
+/// Where the keyboard is between selecting keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct State {
+    position: Position,
+    layers: (usize, Option<usize>),
+}
+
+/// The cheapest way found to reach a state, and the state it came from.
+struct Step {
+    cost: Cost,
+    previous: Option<State>,
+    instructions: Vec<Instruction>,
+}
+
+/// Finds the cheapest program under a cost model that types text on a layout.
+pub(crate) struct Planner<'a> {
+    layout: &'a KeyboardLayout,
+    edge_policy: EdgePolicy,
+    cost_model: &'a dyn CostModel,
+    moves: HashMap<Position, Paths>,
+    locks: Vec<Paths>,
+}
+
+impl<'a> Planner<'a> {
+    pub(crate) fn new(
+        layout: &'a KeyboardLayout,
+        edge_policy: EdgePolicy,
+        cost_model: &'a dyn CostModel,
+    ) -> Self {
+        Planner {
+            layout,
+            edge_policy,
+            cost_model,
+            moves: HashMap::new(),
+            locks: vec![],
+        }
+    }
+
+    /// Plans the program with dynamic programming over the characters of `text`, keeping the
+    /// cheapest way to reach each copy of the key and locked layer after every key, so a copy
+    /// that is further away can win when it is closer to the keys after it. Characters that are
+    /// not on the layout are left out.
+    pub(crate) fn plan(
+        &mut self,
+        position: Position,
+        layers: LayerState,
+        text: &str,
+    ) -> (Program, Position, LayerState) {
+        let start = State {
+            position,
+            layers: (layers.locked, layers.one_shot),
+        };
+        self.lock_paths(layers.locked);
+
+        let mut steps: Vec<BTreeMap<State, Step>> = vec![BTreeMap::from([(
+            start,
+            Step {
+                cost: (0, 0),
+                previous: None,
+                instructions: vec![],
+            },
+        )])];
+
+        for ch in text.chars() {
+            let last = steps.last().expect("there is always a first step");
+            let next = match ch {
+                ' ' => self.output(last, Instruction::Space),
+                '\n' => self.output(last, Instruction::NewLine),
+                _ => {
+                    let targets: Vec<_> = self.layout.find_all(ch).collect();
+                    if targets.is_empty() {
+                        continue;
+                    }
+                    self.select(last, &targets)
+                }
+            };
+            steps.push(next);
+        }
+
+        let mut program = Program::new();
+        let end = steps
+            .last()
+            .and_then(|step| step.iter().min_by_key(|(_, step)| step.cost))
+            .map(|(&state, _)| state)
+            .expect("every step has a state");
+        let mut state = Some(end);
+        let mut instructions = vec![];
+        for step in steps.iter().rev() {
+            let Some(current) = state else { break };
+            let step = &step[&current];
+            instructions.push(&step.instructions);
+            state = step.previous;
+        }
+        instructions
+            .into_iter()
+            .rev()
+            .flatten()
+            .for_each(|instruction| program.push(instruction.clone()));
+
+        let (locked, one_shot) = end.layers;
+        (program, end.position, LayerState { locked, one_shot })
+    }
+
+    /// Types a space or new line from every state, which leaves the state as it was.
+    fn output(
+        &self,
+        last: &BTreeMap<State, Step>,
+        instruction: Instruction,
+    ) -> BTreeMap<State, Step> {
+        let cost = self.cost_model.cost(&instruction);
+
+        last.iter()
+            .map(|(&state, step)| {
+                let step = Step {
+                    cost: add(step.cost, (cost, 1)),
+                    previous: Some(state),
+                    instructions: vec![instruction.clone()],
+                };
+                (state, step)
+            })
+            .collect()
+    }
+
+    /// Selects each copy of a key in `targets` from every state, locking each layer on the way.
+    fn select(
+        &mut self,
+        last: &BTreeMap<State, Step>,
+        targets: &[(usize, Position)],
+    ) -> BTreeMap<State, Step> {
+        let mut next: BTreeMap<State, Step> = BTreeMap::new();
+        let select = (self.cost_model.cost(&Instruction::Select), 1);
+
+        for (&state, step) in last {
+            let (locked, one_shot) = state.layers;
+            // A locked layer that is not on the layout is the node after the layers that are.
+            let from = locked.min(self.layout.layer_count());
+
+            for &(layer, target) in targets {
+                let Some((move_cost, moves)) = self.move_path(state.position, target) else {
+                    continue;
+                };
+                let shift = (self.cost_model.cost(&Instruction::Shift(layer)), 1);
+
+                for lock in 0..self.layout.layer_count() {
+                    let Some((lock_cost, mut instructions)) = self.locks[from].to(lock) else {
+                        continue;
+                    };
+                    let shifted = match one_shot {
+                        Some(one_shot) => one_shot != layer,
+                        None => lock != layer,
+                    };
+                    let mut cost = add(add(step.cost, lock_cost), add(move_cost, select));
+                    if shifted {
+                        cost = add(cost, shift);
+                        instructions.push(Instruction::Shift(layer));
+                    }
+
+                    let next_state = State {
+                        position: target,
+                        layers: (lock, None),
+                    };
+                    if next.get(&next_state).is_none_or(|best| cost < best.cost) {
+                        instructions.extend(moves.iter().cloned());
+                        instructions.push(Instruction::Select);
+                        next.insert(
+                            next_state,
+                            Step {
+                                cost,
+                                previous: Some(state),
+                                instructions,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        next
+    }
+
+    /// The cheapest moves between two positions, with the horizontal moves first.
+    fn move_path(&mut self, from: Position, to: Position) -> Option<(Cost, Vec<Instruction>)> {
+        let (width, height) = self.layout.size();
+        let edge_policy = self.edge_policy;
+        let cost_model = self.cost_model;
+
+        // Wrapping moves go the same way from every key, so the paths from the top left corner
+        // to where `to` is relative to `from` serve every start.
+        let (from, to) = match edge_policy {
+            EdgePolicy::Wrap => (
+                (0, 0),
+                (
+                    (to.0 + width - from.0) % width,
+                    (to.1 + height - from.1) % height,
+                ),
+            ),
+            EdgePolicy::RowWrap => {
+                let len = width * height;
+                let index = (to.1 * width + to.0 + len - (from.1 * width + from.0)) % len;
+                ((0, 0), (index % width, index / width))
+            }
+            EdgePolicy::Clamp | EdgePolicy::Error => (from, to),
+        };
+
+        let paths = self.moves.entry(from).or_insert_with(|| {
+            let across = match edge_policy {
+                EdgePolicy::RowWrap => width * height,
+                _ => width,
+            };
+            let directions = [
+                (Direction::Right, across),
+                (Direction::Left, across),
+                (Direction::Down, height),
+                (Direction::Up, height),
+            ];
+
+            Paths::from(
+                from.1 * width + from.0,
+                width * height,
+                cost_model,
+                |node| {
+                    let position = (node % width, node / width);
+                    directions
+                        .iter()
+                        .flat_map(|&(direction, len)| (1..len).map(move |count| (direction, count)))
+                        .filter_map(|(direction, count)| {
+                            let (x, y) =
+                                edge_policy.step(position, direction, count, (width, height))?;
+                            let instruction = match direction {
+                                Direction::Left => Instruction::Left(count),
+                                Direction::Up => Instruction::Up(count),
+                                Direction::Right => Instruction::Right(count),
+                                Direction::Down => Instruction::Down(count),
+                            };
+                            Some((y * width + x, instruction))
+                        })
+                        .collect()
+                },
+            )
+        });
+        let (cost, mut moves) = paths.to(to.1 * width + to.0)?;
+
+        // Horizontal and vertical moves can be swapped under every edge policy without changing
+        // where they end up or whether they run off the keyboard.
+        moves.sort_by_key(|instruction| {
+            matches!(instruction, Instruction::Up(_) | Instruction::Down(_))
+        });
+
+        Some((cost, moves))
+    }
+
+    /// Finds the cheapest locks from each layer of the layout to every other. When `locked` is
+    /// not on the layout, one more node stands for it after the layers that are, from which
+    /// locking it again goes back to the base layer.
+    fn lock_paths(&mut self, locked: usize) {
+        let layers = self.layout.layer_count();
+        let missing = (locked >= layers).then_some(locked);
+        let len = layers + usize::from(missing.is_some());
+
+        self.locks = (0..len)
+            .map(|node| {
+                Paths::from(node, len, self.cost_model, |node| {
+                    (0..layers)
+                        .chain(missing.filter(|_| node == layers))
+                        .map(|layer| {
+                            // Locking the locked layer again unlocks it.
+                            let locked = if node == layers { locked } else { node };
+                            let next = if layer == locked {
+                                KeyboardLayout::BASE
+                            } else {
+                                layer
+                            };
+                            (next, Instruction::Lock(layer))
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+    }
+}
+
+fn add((cost, count): Cost, (other_cost, other_count): Cost) -> Cost {
+    (cost.saturating_add(other_cost), count + other_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap},
+    };
+
+    use crate::{
+        CostModel, EdgePolicy, GenerateOptions, Instruction, Keyboard, KeyboardError,
+        KeyboardLayout, Keystrokes, LayerState, Manhattan, Position, Program, TokenCount,
+    };
+
+    fn layout() -> KeyboardLayout {
+        KeyboardLayout::new(vec![vec!['a', 'b', 'c'], vec!['d', 'e', 'f']])
+            .unwrap()
+            .with_layer([['A', 'B', 'C'], ['D', 'E', 'F']])
+            .with_layer(vec![vec![Some('1'), None, Some('2')]])
+    }
+
+    /// The cheapest cost of typing `text`, found by searching every instruction the keyboard could
+    /// run next rather than planning key by key.
+    fn cheapest(
+        layout: &KeyboardLayout,
+        edge_policy: EdgePolicy,
+        position: Position,
+        text: &str,
+        cost_model: &dyn CostModel,
+    ) -> usize {
+        type State = (Position, usize, Option<usize>, usize);
+
+        let text: Vec<char> = text.chars().collect();
+        let (width, height) = layout.size();
+        let layers = layout.layer_count();
+        let mut instructions = vec![
+            Instruction::Space,
+            Instruction::NewLine,
+            Instruction::Select,
+        ];
+        for count in 1..width * height {
+            instructions.extend([
+                Instruction::Left(count),
+                Instruction::Up(count),
+                Instruction::Right(count),
+                Instruction::Down(count),
+            ]);
+        }
+        for layer in 0..layers {
+            instructions.extend([Instruction::Shift(layer), Instruction::Lock(layer)]);
+        }
+
+        let mut costs: HashMap<State, usize> = HashMap::new();
+        let mut queue = BinaryHeap::from([Reverse((0, (position, 0, None, 0)))]);
+        while let Some(Reverse((cost, state))) = queue.pop() {
+            let (position, locked, one_shot, typed) = state;
+            if typed == text.len() {
+                return cost;
+            }
+            if costs.get(&state).is_some_and(|&best| best < cost) {
+                continue;
+            }
+
+            for instruction in &instructions {
+                let mut keyboard = Keyboard {
+                    keyboard_layout: layout.clone(),
+                    position,
+                    edge_policy,
+                    layers: LayerState { locked, one_shot },
+                    selected_keys: &mut vec![],
+                };
+                if keyboard
+                    .run_program(&vec![instruction.clone()].into())
+                    .is_err()
+                {
+                    continue;
+                }
+                let typed = match keyboard.selected_keys.as_slice() {
+                    [] => typed,
+                    [key] if *key == text[typed] => typed + 1,
+                    _ => continue,
+                };
+                let next = (
+                    keyboard.position,
+                    keyboard.layers.locked,
+                    keyboard.layers.one_shot,
+                    typed,
+                );
+                let next_cost = cost + cost_model.cost(instruction);
+                if costs.get(&next).is_none_or(|&best| next_cost < best) {
+                    costs.insert(next, next_cost);
+                    queue.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+
+        panic!("{:?} cannot be typed", text)
+    }
+
+    fn assert_cheapest(
+        layout: &KeyboardLayout,
+        edge_policy: EdgePolicy,
+        text: &str,
+        cost_model: &dyn CostModel,
+    ) {
+        let mut keyboard = Keyboard {
+            keyboard_layout: layout.clone(),
+            position: (1, 1),
+            edge_policy,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let instructions = keyboard.generate_instructions_with(text, cost_model);
+        let program: Program = instructions.parse().unwrap();
+
+        keyboard.run_program(&program).unwrap();
+        assert_eq!(keyboard.to_string(), text);
+        assert_eq!(
+            program.cost(cost_model),
+            cheapest(layout, edge_policy, (1, 1), text, cost_model),
+            "{:?} {}: {}",
+            edge_policy,
+            text,
+            instructions
+        );
+    }
+
+    #[test]
+    fn test_should_generate_the_cheapest_program_for_each_cost_model() {
+        let vertical = |instruction: &Instruction| match *instruction {
+            Instruction::Up(count) | Instruction::Down(count) => 4 * count,
+            _ => Keystrokes.cost(instruction),
+        };
+        let cost_models: [&dyn CostModel; 4] = [&TokenCount, &Keystrokes, &Manhattan, &vertical];
+        let duplicates =
+            KeyboardLayout::new(vec![vec!['?', 'a', '?', 'b'], vec!['c', '?', 'a', 'd']])
+                .unwrap()
+                .with_layer([['a', '?']]);
+
+        for edge_policy in [
+            EdgePolicy::Wrap,
+            EdgePolicy::Clamp,
+            EdgePolicy::Error,
+            EdgePolicy::RowWrap,
+        ] {
+            for cost_model in cost_models {
+                for text in ["face", "FaCe 12", "ABC\nDEF", "a1F2fA"] {
+                    assert_cheapest(&layout(), edge_policy, text, cost_model);
+                }
+                for text in ["?a?", "ab?cd", "d?c?a"] {
+                    assert_cheapest(&duplicates, edge_policy, text, cost_model);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_lock_a_layer_when_it_is_cheaper_than_shifting() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: layout(),
+            position: (0, 0),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        assert_eq!(keyboard.generate_instructions("aAb"), "S,^,S,R,S");
+        assert_eq!(keyboard.generate_instructions("aAB"), "S,M,S,R,S");
+        assert_eq!(keyboard.generate_instructions("AB 1"), "M,S,R,S,_,^:2,L,S");
+    }
+
+    #[test]
+    fn test_should_pick_the_copy_of_a_key_that_is_cheapest_for_the_whole_text() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::new(vec![vec!['?', 'a', 'b', 'c', 'd', 'e', '?']])
+                .unwrap(),
+            position: (3, 0),
+            edge_policy: EdgePolicy::Clamp,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        assert_eq!(keyboard.generate_instructions("?a"), "L:3,S,R,S");
+        assert_eq!(keyboard.generate_instructions("?e"), "R:3,S,L,S");
+    }
+
+    #[test]
+    fn test_should_generate_from_a_locked_layer_that_is_not_on_the_layout() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: layout(),
+            position: (0, 0),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        keyboard.run("M:3000").unwrap();
+
+        assert_eq!(keyboard.generate_instructions("a"), "M:0,S");
+        assert_eq!(keyboard.generate_instructions("A"), "M,S");
+    }
+
+    #[test]
+    fn test_should_generate_from_a_position_off_the_layout() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: layout(),
+            position: (4, 3),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        assert_eq!(
+            keyboard.generate("a", &GenerateOptions::default()),
+            Err(KeyboardError::OffLayout((4, 3)))
+        );
+        assert_eq!(keyboard.generate_instructions("a"), "L,D,S");
+        assert_eq!(keyboard.position, (1, 1));
+    }
+
+    #[test]
+    fn test_should_generate_moves_on_a_large_wrapping_layout() {
+        let keys: Vec<char> = ('!'..).take(60 * 60).collect();
+        let layout = KeyboardLayout::new(keys.chunks(60).map(<[char]>::to_vec)).unwrap();
+        let text: String = keys.iter().step_by(71).take(50).collect();
+
+        for edge_policy in [EdgePolicy::Wrap, EdgePolicy::RowWrap] {
+            let mut keyboard = Keyboard {
+                keyboard_layout: layout.clone(),
+                position: (30, 30),
+                edge_policy,
+                layers: LayerState::default(),
+                selected_keys: &mut vec![],
+            };
+            let program: Program = keyboard.generate_instructions(&text).parse().unwrap();
+
+            keyboard.run_program(&program).unwrap();
+            assert_eq!(keyboard.to_string(), text);
+        }
+    }
+}
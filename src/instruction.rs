@@ -0,0 +1,681 @@
+use std::{error, fmt, io::BufRead, ops::Range, str::FromStr};
+
+use crate::KeyboardError;
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::digit1,
+    combinator::{map, opt},
+    sequence::{preceded, tuple},
+    IResult,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Left(usize),
+    Up(usize),
+    Right(usize),
+    Down(usize),
+    Space,
+    NewLine,
+    Select,
+    /// Select the next key from the given layer.
+    Shift(usize),
+    /// Lock in the given layer, or return to the base layer if it is already locked.
+    Lock(usize),
+    /// A token that is not an instruction, which is ignored.
+    Unknown(String),
+}
+
+impl fmt::Display for Instruction {
+    /// Writes the instruction in its canonical form, leaving out a count of 1.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, count) = match self {
+            Instruction::Left(count) => ("L", *count),
+            Instruction::Up(count) => ("U", *count),
+            Instruction::Right(count) => ("R", *count),
+            Instruction::Down(count) => ("D", *count),
+            Instruction::Space => ("_", 1),
+            Instruction::NewLine => ("N", 1),
+            Instruction::Select => ("S", 1),
+            Instruction::Shift(layer) => ("^", *layer),
+            Instruction::Lock(layer) => ("M", *layer),
+            Instruction::Unknown(token) => return write!(f, "{}", token),
+        };
+
+        match count {
+            1 => write!(f, "{}", name),
+            _ => write!(f, "{}:{}", name, count),
+        }
+    }
+}
+
+impl From<&str> for Instruction {
+    fn from(s: &str) -> Self {
+        parse_token(s, ParseMode::Lenient).unwrap_or_else(|_| Instruction::Unknown(s.to_string()))
+    }
+}
+
+/// How strictly instructions are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Ignore anything after a valid instruction in a token, so `RX` is `R`, and turn any other
+    /// token into [`Instruction::Unknown`].
+    #[default]
+    Lenient,
+    /// Reject unknown tokens and tokens with anything after the instruction.
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The token does not start with an instruction.
+    Unknown,
+    /// The token starts with an instruction but has more after it.
+    TrailingInput,
+    /// The count is too large to represent.
+    CountOverflow,
+}
+
+/// An instruction that could not be parsed, found at token `index` and byte range `span` of the
+/// input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub index: usize,
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            ParseErrorKind::Unknown => "unknown instruction",
+            ParseErrorKind::TrailingInput => "unexpected input after instruction",
+            ParseErrorKind::CountOverflow => "count is too large",
+        };
+
+        write!(
+            f,
+            "instruction {} at bytes {}..{}: {}",
+            self.index, self.span.start, self.span.end, reason
+        )
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// An instruction in a [`Program`], with the byte range of the input it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spanned {
+    pub instruction: Instruction,
+    /// `None` for instructions added to a program rather than parsed.
+    pub span: Option<Range<usize>>,
+}
+
+/// A list of instructions, which can be parsed from and written back to the comma separated
+/// syntax or built up in code.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{Instruction, ParseMode, Program};
+///
+/// let program = Program::parse("R:1, S,U:2,Testing", ParseMode::Lenient).unwrap();
+///
+/// assert_eq!(program.steps()[1].span, Some(5..6));
+/// assert_eq!(program.to_string(), "R,S,U:2,Testing");
+///
+/// let mut program = Program::new();
+/// program.push(Instruction::Left(3));
+/// program.push(Instruction::Select);
+///
+/// assert_eq!(program.to_string(), "L:3,S");
+/// assert_eq!(program, "L:3,S".parse().unwrap());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    steps: Vec<Spanned>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Program::default()
+    }
+
+    /// Parses comma separated instructions, keeping where each one came from in `input`.
+    pub fn parse(input: &str, mode: ParseMode) -> Result<Self, ParseError> {
+        let steps = tokens(input)
+            .enumerate()
+            .map(|(index, span)| {
+                let token = &input[span.clone()];
+                let instruction = match parse_token(token, mode) {
+                    Ok(instruction) => instruction,
+                    Err(_) if mode == ParseMode::Lenient => Instruction::Unknown(token.to_string()),
+                    Err(kind) => return Err(ParseError { kind, index, span }),
+                };
+
+                Ok(Spanned {
+                    instruction,
+                    span: Some(span),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Program { steps })
+    }
+
+    pub fn push(&mut self, instruction: Instruction) {
+        self.steps.push(Spanned {
+            instruction,
+            span: None,
+        });
+    }
+
+    pub fn steps(&self) -> &[Spanned] {
+        &self.steps
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.steps.iter().map(|step| &step.instruction)
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl PartialEq for Program {
+    /// Programs are equal when they have the same instructions, wherever they came from.
+    fn eq(&self, other: &Self) -> bool {
+        self.instructions().eq(other.instructions())
+    }
+}
+
+impl Eq for Program {}
+
+impl fmt::Display for Program {
+    /// Writes the instructions in the canonical comma separated syntax.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, instruction) in self.instructions().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    /// Parses the instructions in [`ParseMode::Strict`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Program::parse(s, ParseMode::Strict)
+    }
+}
+
+impl FromIterator<Instruction> for Program {
+    fn from_iter<T: IntoIterator<Item = Instruction>>(iter: T) -> Self {
+        let mut program = Program::new();
+        program.extend(iter);
+        program
+    }
+}
+
+impl Extend<Instruction> for Program {
+    fn extend<T: IntoIterator<Item = Instruction>>(&mut self, iter: T) {
+        iter.into_iter()
+            .for_each(|instruction| self.push(instruction));
+    }
+}
+
+impl From<Vec<Instruction>> for Program {
+    fn from(instructions: Vec<Instruction>) -> Self {
+        instructions.into_iter().collect()
+    }
+}
+
+impl From<Program> for Vec<Instruction> {
+    fn from(program: Program) -> Self {
+        program
+            .steps
+            .into_iter()
+            .map(|step| step.instruction)
+            .collect()
+    }
+}
+
+/// Parses comma separated instructions. Whitespace around each instruction is ignored, and an
+/// empty input has no instructions.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{parse_instructions, Instruction, ParseErrorKind, ParseMode};
+///
+/// assert_eq!(
+///     parse_instructions("R:3, S,RX", ParseMode::Lenient),
+///     Ok(vec![Instruction::Right(3), Instruction::Select, Instruction::Right(1)])
+/// );
+///
+/// let err = parse_instructions("R:3, S,RX", ParseMode::Strict).unwrap_err();
+/// assert_eq!(err.kind, ParseErrorKind::TrailingInput);
+/// assert_eq!((err.index, err.span), (2, 7..9));
+/// ```
+pub fn parse_instructions(input: &str, mode: ParseMode) -> Result<Vec<Instruction>, ParseError> {
+    Program::parse(input, mode).map(Vec::from)
+}
+
+/// Reads comma separated instructions from `reader` one at a time, parsing them the same way as
+/// [`Program::parse`] without reading the whole input first. Only one instruction is held in
+/// memory at a time, however long the input is.
+///
+/// In [`ParseMode::Strict`] an invalid instruction is an error when it is reached, after the
+/// instructions before it have been read.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{read_instructions, Instruction, ParseMode};
+///
+/// let input = "R:3,\n S,\nRX".as_bytes();
+/// let instructions: Vec<_> = read_instructions(input, ParseMode::Lenient)
+///     .map(|step| step.unwrap())
+///     .collect();
+///
+/// assert_eq!(instructions[1].instruction, Instruction::Select);
+/// assert_eq!(instructions[1].span, Some(6..7));
+/// assert_eq!(instructions[2].instruction, Instruction::Right(1));
+/// ```
+pub fn read_instructions<R: BufRead>(reader: R, mode: ParseMode) -> ReadInstructions<R> {
+    ReadInstructions {
+        reader,
+        mode,
+        buffer: vec![],
+        index: 0,
+        offset: 0,
+        after_comma: false,
+        done: false,
+    }
+}
+
+/// An iterator over the instructions read from a [`BufRead`]. See [`read_instructions`].
+#[derive(Debug)]
+pub struct ReadInstructions<R> {
+    reader: R,
+    mode: ParseMode,
+    buffer: Vec<u8>,
+    index: usize,
+    /// The byte offset of the start of the buffer in the input.
+    offset: usize,
+    /// Whether the last token read ended with a comma, so there is one more token at the end.
+    after_comma: bool,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for ReadInstructions<R> {
+    type Item = Result<Spanned, KeyboardError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        self.buffer.clear();
+        let read = loop {
+            match self.reader.read_until(b',', &mut self.buffer) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                read => break read,
+            }
+        };
+        let read = match read {
+            Ok(read) => read,
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err.into()));
+            }
+        };
+
+        let after_comma = self.buffer.last() == Some(&b',');
+        if after_comma {
+            self.buffer.pop();
+        }
+        // Spans are counted in the bytes read, which invalid UTF-8 would not match once replaced.
+        let trimmed = trim(&self.buffer);
+
+        // The last token is only there when it follows a comma, and an input that is all
+        // whitespace has no tokens at all.
+        if !after_comma {
+            self.done = true;
+            if (read == 0 && !self.after_comma) || (trimmed.is_empty() && self.index == 0) {
+                return None;
+            }
+        }
+        self.after_comma = after_comma;
+
+        let span = self.offset + trimmed.start..self.offset + trimmed.end;
+        let index = self.index;
+        self.offset += read;
+        self.index += 1;
+
+        let token = String::from_utf8_lossy(&self.buffer[trimmed]);
+        let token = token.as_ref();
+        let instruction = match parse_token(token, self.mode) {
+            Ok(instruction) => instruction,
+            Err(_) if self.mode == ParseMode::Lenient => Instruction::Unknown(token.to_string()),
+            Err(kind) => {
+                self.done = true;
+                return Some(Err(ParseError { kind, index, span }.into()));
+            }
+        };
+
+        Some(Ok(Spanned {
+            instruction,
+            span: Some(span),
+        }))
+    }
+}
+
+/// The byte range of `bytes` without whitespace around it. Invalid UTF-8 is never whitespace.
+fn trim(bytes: &[u8]) -> Range<usize> {
+    let first = bytes.utf8_chunks().next().map_or("", |chunk| chunk.valid());
+    let last = bytes
+        .utf8_chunks()
+        .last()
+        .filter(|chunk| chunk.invalid().is_empty())
+        .map_or("", |chunk| chunk.valid());
+
+    let start = first.len() - first.trim_start().len();
+    let end = bytes.len() - (last.len() - last.trim_end().len());
+    start..end.max(start)
+}
+
+/// The byte range of each comma separated token in `input`, without surrounding whitespace.
+fn tokens(input: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let tokens = if input.trim().is_empty() {
+        None
+    } else {
+        Some(input.split(','))
+    };
+    let mut start = 0;
+
+    tokens.into_iter().flatten().map(move |token| {
+        let leading = token.len() - token.trim_start().len();
+        let span = start + leading..start + leading + token.trim().len();
+        start += token.len() + 1;
+        span
+    })
+}
+
+pub(crate) fn parse_token(token: &str, mode: ParseMode) -> Result<Instruction, ParseErrorKind> {
+    let (rest, (instruction, count)) =
+        parse_instruction(token).map_err(|_| ParseErrorKind::Unknown)?;
+
+    if mode == ParseMode::Strict && !rest.is_empty() {
+        return Err(ParseErrorKind::TrailingInput);
+    }
+
+    let count = match count {
+        Some(digits) => digits
+            .parse::<usize>()
+            .map_err(|_| ParseErrorKind::CountOverflow)?,
+        None => 1,
+    };
+
+    Ok(match instruction {
+        "L" => Instruction::Left(count),
+        "R" => Instruction::Right(count),
+        "U" => Instruction::Up(count),
+        "D" => Instruction::Down(count),
+        "_" => Instruction::Space,
+        "N" => Instruction::NewLine,
+        "S" => Instruction::Select,
+        "^" => Instruction::Shift(count),
+        "M" => Instruction::Lock(count),
+        _ => unreachable!(),
+    })
+}
+
+fn parse_instruction(input: &str) -> IResult<&str, (&str, Option<&str>)> {
+    alt((
+        tuple((
+            alt((tag("L"), tag("R"), tag("U"), tag("D"), tag("^"), tag("M"))),
+            opt(preceded(tag(":"), digit1)),
+        )),
+        map(alt((tag("S"), tag("_"), tag("N"))), |tag| (tag, None)),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_parse_every_instruction() {
+        assert_eq!(
+            parse_instructions("L,U:2,R:30,D:0,_,N,S,^,^:2,M,M:0", ParseMode::Strict),
+            Ok(vec![
+                Instruction::Left(1),
+                Instruction::Up(2),
+                Instruction::Right(30),
+                Instruction::Down(0),
+                Instruction::Space,
+                Instruction::NewLine,
+                Instruction::Select,
+                Instruction::Shift(1),
+                Instruction::Shift(2),
+                Instruction::Lock(1),
+                Instruction::Lock(0),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_should_ignore_whitespace_around_instructions() {
+        assert_eq!(
+            parse_instructions(" R , S\n", ParseMode::Strict),
+            Ok(vec![Instruction::Right(1), Instruction::Select])
+        );
+        assert_eq!(parse_instructions(" \n", ParseMode::Strict), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_should_keep_unknown_tokens_when_lenient() {
+        assert_eq!(
+            parse_instructions("S,Testing,,R:99999999999999999999,R:", ParseMode::Lenient),
+            Ok(vec![
+                Instruction::Select,
+                Instruction::Unknown("Testing".to_string()),
+                Instruction::Unknown("".to_string()),
+                Instruction::Unknown("R:99999999999999999999".to_string()),
+                Instruction::Right(1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_should_reject_unknown_tokens_when_strict() {
+        assert_eq!(
+            parse_instructions("S, Testing", ParseMode::Strict),
+            Err(ParseError {
+                kind: ParseErrorKind::Unknown,
+                index: 1,
+                span: 3..10
+            })
+        );
+        assert_eq!(
+            parse_instructions("S,,S", ParseMode::Strict),
+            Err(ParseError {
+                kind: ParseErrorKind::Unknown,
+                index: 1,
+                span: 2..2
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_reject_partially_consumed_tokens_when_strict() {
+        assert_eq!(
+            parse_instructions("S,R:", ParseMode::Strict),
+            Err(ParseError {
+                kind: ParseErrorKind::TrailingInput,
+                index: 1,
+                span: 2..4
+            })
+        );
+        assert_eq!(
+            parse_instructions("SS", ParseMode::Strict),
+            Err(ParseError {
+                kind: ParseErrorKind::TrailingInput,
+                index: 0,
+                span: 0..2
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_only_take_counts_on_moves_shifts_and_locks() {
+        assert_eq!(
+            parse_instructions("S,_:9", ParseMode::Strict),
+            Err(ParseError {
+                kind: ParseErrorKind::TrailingInput,
+                index: 1,
+                span: 2..5
+            })
+        );
+        assert!("S:3".parse::<Program>().is_err());
+        assert_eq!(
+            parse_instructions("S:3,_:9,N:0", ParseMode::Lenient),
+            Ok(vec![
+                Instruction::Select,
+                Instruction::Space,
+                Instruction::NewLine
+            ])
+        );
+    }
+
+    #[test]
+    fn test_should_write_instructions_in_canonical_form() {
+        let program: Program = vec![
+            Instruction::Left(1),
+            Instruction::Up(0),
+            Instruction::Right(12),
+            Instruction::Down(1),
+            Instruction::Space,
+            Instruction::NewLine,
+            Instruction::Select,
+            Instruction::Shift(1),
+            Instruction::Shift(2),
+            Instruction::Lock(1),
+            Instruction::Lock(0),
+        ]
+        .into();
+
+        assert_eq!(program.to_string(), "L,U:0,R:12,D,_,N,S,^,^:2,M,M:0");
+        assert_eq!(Program::new().to_string(), "");
+    }
+
+    #[test]
+    fn test_should_round_trip_programs() {
+        for input in ["R,S,U,L:3,S,D,R:6,S,S,U,S", "^:2,M,_,N,D:0", ""] {
+            let program: Program = input.parse().unwrap();
+            assert_eq!(program.to_string(), input);
+            assert_eq!(program.to_string().parse::<Program>(), Ok(program));
+        }
+
+        let program = Program::parse("R:1, S:1,Testing,R:", ParseMode::Lenient).unwrap();
+        assert_eq!(program.to_string(), "R,S,Testing,R");
+    }
+
+    #[test]
+    fn test_should_read_instructions_the_same_way_as_parsing_them() {
+        let inputs = [
+            "",
+            "  \n",
+            ",",
+            "R,S,U,L:3,S,D,R:6,S,S,U,S",
+            " R:10 , S,\nU, ",
+            "R,S,",
+            "R:1,Testing,SX,L:99999999999999999999999,^:2",
+            "é,R",
+        ];
+
+        for input in inputs {
+            for mode in [ParseMode::Lenient, ParseMode::Strict] {
+                let read: Result<Vec<Spanned>, KeyboardError> =
+                    read_instructions(std::io::BufReader::with_capacity(1, input.as_bytes()), mode)
+                        .collect();
+                let parsed = Program::parse(input, mode)
+                    .map(|program| program.steps().to_vec())
+                    .map_err(KeyboardError::from);
+
+                assert_eq!(read, parsed, "{:?} {:?}", input, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_count_spans_in_the_bytes_read() {
+        let input = b"R, \xff\xfe ,S";
+        let read: Vec<Spanned> = read_instructions(&input[..], ParseMode::Lenient)
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(
+            read[1].instruction,
+            Instruction::Unknown("\u{fffd}\u{fffd}".to_string())
+        );
+        assert_eq!(
+            read.iter()
+                .map(|step| step.span.clone())
+                .collect::<Vec<_>>(),
+            vec![Some(0..1), Some(3..5), Some(7..8)]
+        );
+    }
+
+    #[test]
+    fn test_should_stop_reading_at_the_first_invalid_instruction() {
+        let mut instructions = read_instructions("R,X,S".as_bytes(), ParseMode::Strict);
+
+        assert!(instructions.next().unwrap().is_ok());
+        assert_eq!(
+            instructions.next(),
+            Some(Err(KeyboardError::Parse(ParseError {
+                kind: ParseErrorKind::Unknown,
+                index: 1,
+                span: 2..3
+            })))
+        );
+        assert_eq!(instructions.next(), None);
+    }
+
+    #[test]
+    fn test_should_keep_the_span_of_each_instruction() {
+        let program = Program::parse("R:10 , S,\nU", ParseMode::Strict).unwrap();
+        let spans: Vec<_> = program
+            .steps()
+            .iter()
+            .map(|step| step.span.clone())
+            .collect();
+
+        assert_eq!(spans, vec![Some(0..4), Some(7..8), Some(10..11)]);
+    }
+
+    #[test]
+    fn test_should_reject_counts_that_overflow() {
+        assert_eq!(
+            parse_instructions("L,R:99999999999999999999", ParseMode::Strict),
+            Err(ParseError {
+                kind: ParseErrorKind::CountOverflow,
+                index: 1,
+                span: 2..24
+            })
+        );
+    }
+}
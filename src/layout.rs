@@ -0,0 +1,313 @@
+use std::{error, fmt};
+
+use crate::Position;
+
+mod file;
+mod library;
+
+pub use file::{LayoutFormat, Location};
+pub use library::{ALPHABETICAL, AZERTY, COLEMAK, DVORAK, PHONE};
+
+/// A grid of keys, sized at runtime.
+///
+/// Rows may have different lengths and may contain empty cells with no key. The cursor moves
+/// over the bounding rectangle of the rows, so it can pass over and land on empty cells, including
+/// the cells past the end of a short row. Selecting an empty cell selects nothing.
+///
+/// A layout can also have extra layers of keys on top of the base layer, such as
+/// [`KeyboardLayout::SHIFT`] and [`KeyboardLayout::SYMBOLS`], which the keyboard switches between
+/// to select keys that are not on the base layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardLayout {
+    width: usize,
+    height: usize,
+    layers: Vec<Vec<Vec<Option<char>>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The layout has no keys.
+    Empty,
+    /// A layout file has something other than a single, quoted or empty cell.
+    InvalidCell { at: Location },
+    /// A layout file has a row with no cells.
+    EmptyRow { at: Location },
+    /// A layout file has a row with `found` cells where the first row has `expected`.
+    NotRectangular {
+        at: Location,
+        expected: usize,
+        found: usize,
+    },
+    /// A TOML or JSON layout file could not be read.
+    Syntax(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::Empty => write!(f, "layout has no keys"),
+            LayoutError::InvalidCell { at } => write!(
+                f,
+                "{}: expected a key, a quoted key or \"\" for an empty cell",
+                at
+            ),
+            LayoutError::EmptyRow { at } => write!(f, "{}: row has no cells", at),
+            LayoutError::NotRectangular {
+                at,
+                expected,
+                found,
+            } => write!(f, "{}: row has {} cells, expected {}", at, found, expected),
+            LayoutError::Syntax(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for LayoutError {}
+
+impl KeyboardLayout {
+    pub const BASE: usize = 0;
+    pub const SHIFT: usize = 1;
+    pub const SYMBOLS: usize = 2;
+
+    /// Builds a layout from the rows of its base layer, top to bottom. Cells are either keys or
+    /// `None` for an empty cell, and rows may be of different lengths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let layout = keyboard_madness::KeyboardLayout::new(vec![
+    ///     vec!['1', '2', '3'],
+    ///     vec!['4', '5', '6'],
+    ///     vec!['7', '8', '9'],
+    /// ])
+    /// .unwrap();
+    ///
+    /// assert_eq!((layout.width(), layout.height()), (3, 3));
+    /// assert_eq!(layout.get((2, 1)), Some('6'));
+    ///
+    /// let remote = keyboard_madness::KeyboardLayout::new(vec![
+    ///     vec![None, Some('^'), None],
+    ///     vec![Some('<'), Some('O'), Some('>')],
+    ///     vec![None, Some('v')],
+    /// ])
+    /// .unwrap();
+    ///
+    /// assert_eq!(remote.row_len(2), 2);
+    /// assert_eq!(remote.get((0, 0)), None);
+    /// assert_eq!(remote.get((1, 2)), Some('v'));
+    /// ```
+    pub fn new<R, C>(rows: impl IntoIterator<Item = R>) -> Result<Self, LayoutError>
+    where
+        R: IntoIterator<Item = C>,
+        C: Into<Option<char>>,
+    {
+        let layout = KeyboardLayout {
+            width: 0,
+            height: 0,
+            layers: vec![],
+        }
+        .with_layer(rows);
+
+        if !layout.layers[Self::BASE]
+            .iter()
+            .flatten()
+            .any(Option::is_some)
+        {
+            return Err(LayoutError::Empty);
+        }
+
+        Ok(layout)
+    }
+
+    /// Adds the next layer on top of the layout, so the first call adds
+    /// [`KeyboardLayout::SHIFT`]. The bounding rectangle grows to fit the layer if needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::KeyboardLayout;
+    ///
+    /// let layout = KeyboardLayout::new(vec![vec!['A', 'B', 'C']])
+    ///     .unwrap()
+    ///     .with_layer(vec![vec!['a', 'b', 'c']])
+    ///     .with_layer(vec![vec![None, Some('!')], vec![Some('?')]]);
+    ///
+    /// assert_eq!(layout.layer_count(), 3);
+    /// assert_eq!(layout.size(), (3, 2));
+    /// assert_eq!(layout.get_on(KeyboardLayout::SHIFT, (2, 0)), Some('c'));
+    /// assert_eq!(layout.get_on(KeyboardLayout::SYMBOLS, (0, 1)), Some('?'));
+    /// assert_eq!(layout.get((0, 1)), None);
+    /// ```
+    pub fn with_layer<R, C>(mut self, rows: impl IntoIterator<Item = R>) -> Self
+    where
+        R: IntoIterator<Item = C>,
+        C: Into<Option<char>>,
+    {
+        let rows: Vec<Vec<Option<char>>> = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(Into::into).collect())
+            .collect();
+
+        self.width = rows.iter().map(Vec::len).fold(self.width, usize::max);
+        self.height = self.height.max(rows.len());
+        self.layers.push(rows);
+        self
+    }
+
+    /// The length of the longest row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The `(width, height)` of the bounding rectangle of the layout.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+
+    /// The number of layers, including the base layer.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// The number of cells in row `y` of the base layer, which may be less than
+    /// [`KeyboardLayout::width`].
+    pub fn row_len(&self, y: usize) -> usize {
+        self.layers[Self::BASE].get(y).map_or(0, Vec::len)
+    }
+
+    /// The key at `(x, y)` on the base layer, or `None` if the cell is empty or off the layout.
+    pub fn get(&self, position: Position) -> Option<char> {
+        self.get_on(Self::BASE, position)
+    }
+
+    /// The key at `(x, y)` on `layer`, or `None` if the cell is empty, off the layout or the
+    /// layer does not exist.
+    pub fn get_on(&self, layer: usize, (x, y): Position) -> Option<char> {
+        *self.layers.get(layer)?.get(y)?.get(x)?
+    }
+
+    /// The cells of each row of the base layer.
+    pub fn rows(&self) -> impl Iterator<Item = &[Option<char>]> {
+        self.rows_on(Self::BASE)
+    }
+
+    /// The cells of each row of `layer`, which has no rows if the layer does not exist.
+    pub fn rows_on(&self, layer: usize) -> impl Iterator<Item = &[Option<char>]> {
+        self.layers
+            .get(layer)
+            .into_iter()
+            .flatten()
+            .map(Vec::as_slice)
+    }
+
+    /// The layer and position of the first copy of `key`, searching the layers in order.
+    pub fn find(&self, key: char) -> Option<(usize, Position)> {
+        self.find_all(key).next()
+    }
+
+    /// The layer and position of every copy of `key`, layer by layer and row by row.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::KeyboardLayout;
+    ///
+    /// let layout = KeyboardLayout::new(vec![vec!['?', 'A', '?']])
+    ///     .unwrap()
+    ///     .with_layer([['?']]);
+    ///
+    /// assert_eq!(
+    ///     layout.find_all('?').collect::<Vec<_>>(),
+    ///     vec![(0, (0, 0)), (0, (2, 0)), (1, (0, 0))]
+    /// );
+    /// ```
+    pub fn find_all(&self, key: char) -> impl Iterator<Item = (usize, Position)> + '_ {
+        self.layers
+            .iter()
+            .enumerate()
+            .flat_map(move |(layer, rows)| {
+                rows.iter().enumerate().flat_map(move |(y, row)| {
+                    row.iter()
+                        .enumerate()
+                        .filter(move |&(_, &cell)| cell == Some(key))
+                        .map(move |(x, _)| (layer, (x, y)))
+                })
+            })
+    }
+}
+
+impl<const W: usize, const H: usize> From<[[char; W]; H]> for KeyboardLayout {
+    fn from(rows: [[char; W]; H]) -> Self {
+        KeyboardLayout::new(rows).expect("layout has no keys")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_build_a_layout_of_any_size() {
+        let layout =
+            KeyboardLayout::new(vec![vec!['A', 'B'], vec!['C', 'D'], vec!['E', 'F']]).unwrap();
+
+        assert_eq!(layout.size(), (2, 3));
+        assert_eq!(layout.get((0, 2)), Some('E'));
+        assert_eq!(layout.get((2, 0)), None);
+        assert_eq!(layout.get((0, 3)), None);
+    }
+
+    #[test]
+    fn test_should_reject_empty_layouts() {
+        assert_eq!(
+            KeyboardLayout::new(Vec::<Vec<char>>::new()),
+            Err(LayoutError::Empty)
+        );
+        assert_eq!(
+            KeyboardLayout::new(vec![vec![None, None], vec![None]]),
+            Err(LayoutError::Empty)
+        );
+    }
+
+    #[test]
+    fn test_should_pad_short_rows_with_empty_cells() {
+        let layout = KeyboardLayout::new(vec![
+            vec![Some('A'), None, Some('B')],
+            vec![Some('C')],
+            vec![],
+            vec![None, Some('D')],
+        ])
+        .unwrap();
+
+        assert_eq!(layout.size(), (3, 4));
+        assert_eq!(
+            (0..4).map(|y| layout.row_len(y)).collect::<Vec<_>>(),
+            vec![3, 1, 0, 2]
+        );
+        assert_eq!(layout.get((1, 0)), None);
+        assert_eq!(layout.get((2, 1)), None);
+        assert_eq!(layout.get((1, 3)), Some('D'));
+        assert_eq!(layout.rows().nth(1), Some(&[Some('C')][..]));
+    }
+
+    #[test]
+    fn test_should_find_keys_on_every_layer() {
+        let layout = KeyboardLayout::new(vec![vec!['A', 'B']])
+            .unwrap()
+            .with_layer(vec![vec!['a', 'b']])
+            .with_layer(vec![vec![None, None], vec![Some('!'), Some('A')]]);
+
+        assert_eq!(layout.size(), (2, 2));
+        assert_eq!(layout.find('B'), Some((KeyboardLayout::BASE, (1, 0))));
+        assert_eq!(layout.find('a'), Some((KeyboardLayout::SHIFT, (0, 0))));
+        assert_eq!(layout.find('!'), Some((KeyboardLayout::SYMBOLS, (0, 1))));
+        assert_eq!(layout.find('A'), Some((KeyboardLayout::BASE, (0, 0))));
+        assert_eq!(layout.find('?'), None);
+        assert_eq!(layout.get_on(3, (0, 0)), None);
+        assert_eq!(layout.rows_on(3).count(), 0);
+    }
+}
@@ -0,0 +1,443 @@
+use std::{fmt, iter, ops::Range, path::Path, str::FromStr};
+
+use serde::Deserialize;
+
+use super::{KeyboardLayout, LayoutError};
+
+/// The file formats a layout can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutFormat {
+    /// Plain text with one row per line and cells separated by whitespace. A cell is a single
+    /// character, a character in double quotes such as `" "`, or `""` for an empty cell. Each
+    /// extra layer follows a line of `---`.
+    Grid,
+    /// A TOML document with a `rows` array of rows, each an array of one character strings with
+    /// `""` for an empty cell, and an optional `layers` array with the rows of each extra layer.
+    Toml,
+    /// The JSON equivalent of [`LayoutFormat::Toml`].
+    Json,
+}
+
+impl LayoutFormat {
+    /// Picks the format from the extension of `path`, defaulting to [`LayoutFormat::Grid`].
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => LayoutFormat::Toml,
+            Some("json") => LayoutFormat::Json,
+            _ => LayoutFormat::Grid,
+        }
+    }
+}
+
+/// Where a problem was found in a layout file, counting from 1. For TOML and JSON layouts the
+/// line is the row number and the column is the cell number within that row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+struct Row {
+    start: Location,
+    end: Location,
+    cells: Vec<(Option<char>, Location)>,
+}
+
+#[derive(Deserialize)]
+struct LayoutDocument {
+    rows: Vec<Vec<String>>,
+    #[serde(default)]
+    layers: Vec<Vec<Vec<String>>>,
+}
+
+impl KeyboardLayout {
+    /// Parses and validates a layout file. Rows must all have the same number of cells, with
+    /// empty cells written out. A key may appear more than once, such as a second `?`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{KeyboardLayout, LayoutFormat};
+    ///
+    /// let grid = r#"
+    /// "1" "2" "3"
+    /// "4" "5" "6"
+    /// ""  "0" ""
+    /// "#;
+    /// let toml = r#"rows = [["1", "2", "3"], ["4", "5", "6"], ["", "0", ""]]"#;
+    ///
+    /// let layout = KeyboardLayout::parse(grid, LayoutFormat::Grid).unwrap();
+    ///
+    /// assert_eq!(layout.get((1, 2)), Some('0'));
+    /// assert_eq!(KeyboardLayout::parse(toml, LayoutFormat::Toml).unwrap(), layout);
+    /// ```
+    pub fn parse(source: &str, format: LayoutFormat) -> Result<Self, LayoutError> {
+        let layers = match format {
+            LayoutFormat::Grid => parse_grid(source)?,
+            LayoutFormat::Toml => from_document(
+                toml::from_str(source).map_err(|err| LayoutError::Syntax(err.to_string()))?,
+            )?,
+            LayoutFormat::Json => from_document(
+                serde_json::from_str(source).map_err(|err| LayoutError::Syntax(err.to_string()))?,
+            )?,
+        };
+
+        let width = layers[0].first().map_or(0, |row| row.cells.len());
+        for rows in &layers {
+            validate(rows, width)?;
+        }
+
+        let mut layers = layers.into_iter().map(|rows| {
+            rows.into_iter()
+                .map(|row| row.cells.into_iter().map(|(cell, _)| cell))
+        });
+        let base = layers.next().expect("layout has a base layer");
+
+        Ok(layers.fold(KeyboardLayout::new(base)?, KeyboardLayout::with_layer))
+    }
+}
+
+impl FromStr for KeyboardLayout {
+    type Err = LayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeyboardLayout::parse(s, LayoutFormat::Grid)
+    }
+}
+
+fn parse_grid(source: &str) -> Result<Vec<Vec<Row>>, LayoutError> {
+    let lines: Vec<&str> = source.lines().collect();
+    let separators: Vec<usize> = (0..lines.len())
+        .filter(|&index| lines[index].trim() == "---")
+        .collect();
+    let starts = iter::once(0).chain(separators.iter().map(|index| index + 1));
+    let ends = separators.iter().copied().chain(iter::once(lines.len()));
+
+    starts
+        .zip(ends)
+        .enumerate()
+        .map(|(layer, (start, end))| {
+            let rows = parse_layer(&lines, start..end)?;
+            if layer > 0 && rows.is_empty() {
+                return Err(LayoutError::EmptyRow {
+                    at: Location {
+                        line: start + 1,
+                        column: 1,
+                    },
+                });
+            }
+            Ok(rows)
+        })
+        .collect()
+}
+
+fn parse_layer(lines: &[&str], range: Range<usize>) -> Result<Vec<Row>, LayoutError> {
+    let is_blank = |index: &usize| lines[*index].trim().is_empty();
+    let first = range.clone().find(|index| !is_blank(index));
+    let last = range.rev().find(|index| !is_blank(index));
+
+    match (first, last) {
+        (Some(first), Some(last)) => (first..=last)
+            .map(|index| parse_row(lines[index], index + 1))
+            .collect(),
+        _ => Ok(vec![]),
+    }
+}
+
+fn parse_row(line: &str, line_number: usize) -> Result<Row, LayoutError> {
+    let chars: Vec<char> = line.chars().collect();
+    let ends_at = |i: usize| chars.get(i).is_none_or(|ch| ch.is_whitespace());
+    let mut cells = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let at = Location {
+            line: line_number,
+            column: i + 1,
+        };
+        let (cell, len) = if chars[i] == '"' && chars.get(i + 2) == Some(&'"') && ends_at(i + 3) {
+            (Some(chars[i + 1]), 3)
+        } else if chars[i] == '"' && chars.get(i + 1) == Some(&'"') && ends_at(i + 2) {
+            (None, 2)
+        } else if ends_at(i + 1) {
+            (Some(chars[i]), 1)
+        } else {
+            return Err(LayoutError::InvalidCell { at });
+        };
+
+        cells.push((cell, at));
+        i += len;
+    }
+
+    Ok(Row {
+        start: Location {
+            line: line_number,
+            column: 1,
+        },
+        end: Location {
+            line: line_number,
+            column: chars.len() + 1,
+        },
+        cells,
+    })
+}
+
+fn from_document(document: LayoutDocument) -> Result<Vec<Vec<Row>>, LayoutError> {
+    iter::once(document.rows)
+        .chain(document.layers)
+        .map(from_rows)
+        .collect()
+}
+
+fn from_rows(rows: Vec<Vec<String>>) -> Result<Vec<Row>, LayoutError> {
+    rows.into_iter()
+        .enumerate()
+        .map(|(row, cells)| {
+            let location = |column| Location {
+                line: row + 1,
+                column,
+            };
+            let end = location(cells.len() + 1);
+            let cells = cells
+                .into_iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    let at = location(column + 1);
+                    let mut chars = cell.chars();
+                    match (chars.next(), chars.next()) {
+                        (key, None) => Ok((key, at)),
+                        _ => Err(LayoutError::InvalidCell { at }),
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+
+            Ok(Row {
+                start: location(1),
+                end,
+                cells,
+            })
+        })
+        .collect()
+}
+
+fn validate(rows: &[Row], width: usize) -> Result<(), LayoutError> {
+    for row in rows {
+        if row.cells.is_empty() {
+            return Err(LayoutError::EmptyRow { at: row.start });
+        }
+
+        if row.cells.len() != width {
+            return Err(LayoutError::NotRectangular {
+                at: row.cells.get(width).map_or(row.end, |&(_, at)| at),
+                expected: width,
+                found: row.cells.len(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_parse_the_readme_grid() {
+        let layout: KeyboardLayout = r#"
+"1" "2" "3" "4" "5" "6" "7" "8" "9" "0"
+"Q" "W" "E" "R" "T" "Y" "U" "I" "O" "P"
+"A" "S" "D" "F" "G" "H" "J" "K" "L" ";"
+"Z" "X" "C" "V" "B" "N" "M" "," "." "?"
+"#
+        .parse()
+        .unwrap();
+
+        assert_eq!(layout, KeyboardLayout::from(crate::KEYS));
+    }
+
+    #[test]
+    fn test_should_parse_bare_quoted_and_empty_cells() {
+        let layout: KeyboardLayout = "a \" \"x\"\n\" \" \"\" b".parse().unwrap();
+
+        assert_eq!(layout.size(), (3, 2));
+        assert_eq!(layout.get((1, 0)), Some('"'));
+        assert_eq!(layout.get((2, 0)), Some('x'));
+        assert_eq!(layout.get((0, 1)), Some(' '));
+        assert_eq!(layout.get((1, 1)), None);
+    }
+
+    #[test]
+    fn test_should_report_invalid_cells() {
+        assert_eq!(
+            "A B\nC DE".parse::<KeyboardLayout>(),
+            Err(LayoutError::InvalidCell {
+                at: Location { line: 2, column: 3 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_report_empty_rows() {
+        assert_eq!(
+            "\nA B\n\nC D\n\n".parse::<KeyboardLayout>(),
+            Err(LayoutError::EmptyRow {
+                at: Location { line: 3, column: 1 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_report_rows_that_are_not_rectangular() {
+        assert_eq!(
+            "A B C\nD E".parse::<KeyboardLayout>(),
+            Err(LayoutError::NotRectangular {
+                at: Location { line: 2, column: 4 },
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            "A B\nC D  E".parse::<KeyboardLayout>(),
+            Err(LayoutError::NotRectangular {
+                at: Location { line: 2, column: 6 },
+                expected: 2,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_allow_duplicate_keys() {
+        let layout = "A B\n\"\" A".parse::<KeyboardLayout>().unwrap();
+
+        assert_eq!(layout.find('A'), Some((KeyboardLayout::BASE, (0, 0))));
+        assert_eq!(
+            layout.find_all('A').collect::<Vec<_>>(),
+            vec![
+                (KeyboardLayout::BASE, (0, 0)),
+                (KeyboardLayout::BASE, (1, 1))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_parse_toml_and_json_layouts() {
+        let expected: KeyboardLayout = "1 2 3\n4 5 6\n7 8 9\n* 0 #".parse().unwrap();
+        let toml = r##"
+rows = [
+    ["1", "2", "3"],
+    ["4", "5", "6"],
+    ["7", "8", "9"],
+    ["*", "0", "#"],
+]
+"##;
+        let json =
+            r##"{"rows": [["1", "2", "3"], ["4", "5", "6"], ["7", "8", "9"], ["*", "0", "#"]]}"##;
+
+        assert_eq!(
+            KeyboardLayout::parse(toml, LayoutFormat::Toml),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            KeyboardLayout::parse(json, LayoutFormat::Json),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_should_validate_toml_and_json_layouts() {
+        assert_eq!(
+            KeyboardLayout::parse(r#"rows = [["A", "BC"]]"#, LayoutFormat::Toml),
+            Err(LayoutError::InvalidCell {
+                at: Location { line: 1, column: 2 }
+            })
+        );
+        assert_eq!(
+            KeyboardLayout::parse(r#"{"rows": [["A", "B"], []]}"#, LayoutFormat::Json),
+            Err(LayoutError::EmptyRow {
+                at: Location { line: 2, column: 1 }
+            })
+        );
+        assert!(matches!(
+            KeyboardLayout::parse(r#"{"rows": "#, LayoutFormat::Json),
+            Err(LayoutError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_should_parse_layers() {
+        let grid = "A B\nC D\n---\na b\nc d\n---\n\n! \"\"\n";
+        let toml = r#"
+rows = [["A", "B"], ["C", "D"]]
+layers = [[["a", "b"], ["c", "d"]], [["!", ""]]]
+"#;
+        let expected = KeyboardLayout::new(vec![vec!['A', 'B'], vec!['C', 'D']])
+            .unwrap()
+            .with_layer(vec![vec!['a', 'b'], vec!['c', 'd']])
+            .with_layer(vec![vec![Some('!'), None]]);
+
+        assert_eq!(grid.parse::<KeyboardLayout>(), Ok(expected.clone()));
+        assert_eq!(
+            KeyboardLayout::parse(toml, LayoutFormat::Toml),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_should_validate_each_layer() {
+        assert_eq!(
+            "A B\n---\na b c".parse::<KeyboardLayout>(),
+            Err(LayoutError::NotRectangular {
+                at: Location { line: 3, column: 5 },
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            "A B\n---\nA a"
+                .parse::<KeyboardLayout>()
+                .map(|layout| layout.find('a')),
+            Ok(Some((KeyboardLayout::SHIFT, (1, 0))))
+        );
+        assert_eq!(
+            "A B\n---\na a"
+                .parse::<KeyboardLayout>()
+                .map(|layout| layout.find_all('a').count()),
+            Ok(2)
+        );
+        assert_eq!(
+            "A B\n---\n\n---\na b".parse::<KeyboardLayout>(),
+            Err(LayoutError::EmptyRow {
+                at: Location { line: 3, column: 1 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_pick_the_format_from_the_file_extension() {
+        assert_eq!(
+            LayoutFormat::from_path(Path::new("layouts/dvorak.toml")),
+            LayoutFormat::Toml
+        );
+        assert_eq!(
+            LayoutFormat::from_path(Path::new("phone.json")),
+            LayoutFormat::Json
+        );
+        assert_eq!(
+            LayoutFormat::from_path(Path::new("remote.txt")),
+            LayoutFormat::Grid
+        );
+    }
+}
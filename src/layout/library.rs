@@ -0,0 +1,192 @@
+use super::KeyboardLayout;
+
+const QWERTY_SHIFT: [[char; 10]; 4] = [
+    ['!', '@', '#', '$', '%', '^', '&', '*', '(', ')'],
+    ['q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p'],
+    ['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', ':'],
+    ['z', 'x', 'c', 'v', 'b', 'n', 'm', '<', '>', '/'],
+];
+
+pub const DVORAK: [[char; 10]; 4] = [
+    ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'],
+    ['\'', ',', '.', 'P', 'Y', 'F', 'G', 'C', 'R', 'L'],
+    ['A', 'O', 'E', 'U', 'I', 'D', 'H', 'T', 'N', 'S'],
+    [';', 'Q', 'J', 'K', 'X', 'B', 'M', 'W', 'V', 'Z'],
+];
+
+const DVORAK_SHIFT: [[char; 10]; 4] = [
+    ['!', '@', '#', '$', '%', '^', '&', '*', '(', ')'],
+    ['"', '<', '>', 'p', 'y', 'f', 'g', 'c', 'r', 'l'],
+    ['a', 'o', 'e', 'u', 'i', 'd', 'h', 't', 'n', 's'],
+    [':', 'q', 'j', 'k', 'x', 'b', 'm', 'w', 'v', 'z'],
+];
+
+pub const AZERTY: [[char; 10]; 4] = [
+    ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'],
+    ['A', 'Z', 'E', 'R', 'T', 'Y', 'U', 'I', 'O', 'P'],
+    ['Q', 'S', 'D', 'F', 'G', 'H', 'J', 'K', 'L', 'M'],
+    ['W', 'X', 'C', 'V', 'B', 'N', ',', ';', ':', '!'],
+];
+
+const AZERTY_SHIFT: [[char; 10]; 4] = [
+    ['&', 'é', '"', '\'', '(', '-', 'è', '_', 'ç', 'à'],
+    ['a', 'z', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p'],
+    ['q', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'm'],
+    ['w', 'x', 'c', 'v', 'b', 'n', '?', '.', '/', '§'],
+];
+
+pub const COLEMAK: [[char; 10]; 4] = [
+    ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'],
+    ['Q', 'W', 'F', 'P', 'G', 'J', 'L', 'U', 'Y', ';'],
+    ['A', 'R', 'S', 'T', 'D', 'H', 'N', 'E', 'I', 'O'],
+    ['Z', 'X', 'C', 'V', 'B', 'K', 'M', ',', '.', '?'],
+];
+
+const COLEMAK_SHIFT: [[char; 10]; 4] = [
+    ['!', '@', '#', '$', '%', '^', '&', '*', '(', ')'],
+    ['q', 'w', 'f', 'p', 'g', 'j', 'l', 'u', 'y', ':'],
+    ['a', 'r', 's', 't', 'd', 'h', 'n', 'e', 'i', 'o'],
+    ['z', 'x', 'c', 'v', 'b', 'k', 'm', '<', '>', '/'],
+];
+
+/// The symbols layer shared by the 10x4 layouts, for the punctuation missing from their base and
+/// shift layers.
+const SYMBOL_KEYS: [&[char]; 2] = [
+    &['`', '~', '-', '_', '=', '+', '[', ']', '{', '}'],
+    &['\\', '|', '\'', '"'],
+];
+
+/// The A to Z grid found on TV and games console search screens.
+pub const ALPHABETICAL: [[char; 6]; 6] = [
+    ['A', 'B', 'C', 'D', 'E', 'F'],
+    ['G', 'H', 'I', 'J', 'K', 'L'],
+    ['M', 'N', 'O', 'P', 'Q', 'R'],
+    ['S', 'T', 'U', 'V', 'W', 'X'],
+    ['Y', 'Z', '1', '2', '3', '4'],
+    ['5', '6', '7', '8', '9', '0'],
+];
+
+const ALPHABETICAL_SHIFT: [[char; 6]; 6] = [
+    ['a', 'b', 'c', 'd', 'e', 'f'],
+    ['g', 'h', 'i', 'j', 'k', 'l'],
+    ['m', 'n', 'o', 'p', 'q', 'r'],
+    ['s', 't', 'u', 'v', 'w', 'x'],
+    ['y', 'z', '.', ',', '?', '!'],
+    ['-', '\'', '&', '@', '(', ')'],
+];
+
+pub const PHONE: [[char; 3]; 4] = [
+    ['1', '2', '3'],
+    ['4', '5', '6'],
+    ['7', '8', '9'],
+    ['*', '0', '#'],
+];
+
+impl KeyboardLayout {
+    /// The names of the built-in layouts, for use with [`KeyboardLayout::named`].
+    pub const NAMES: [&'static str; 6] = [
+        "qwerty",
+        "dvorak",
+        "azerty",
+        "colemak",
+        "alphabetical",
+        "phone",
+    ];
+
+    /// Looks up one of the built-in layouts listed in [`KeyboardLayout::NAMES`]. The 10x4
+    /// layouts have a [`KeyboardLayout::SHIFT`] layer with lowercase letters and shifted symbols
+    /// and a [`KeyboardLayout::SYMBOLS`] layer with the remaining punctuation, and the
+    /// alphabetical layout has a shift layer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::KeyboardLayout;
+    ///
+    /// let phone = KeyboardLayout::named("phone").unwrap();
+    ///
+    /// assert_eq!(phone.size(), (3, 4));
+    /// assert_eq!(phone.layer_count(), 1);
+    ///
+    /// let qwerty = KeyboardLayout::named("qwerty").unwrap();
+    ///
+    /// assert_eq!(qwerty.get((4, 2)), Some('G'));
+    /// assert_eq!(qwerty.get_on(KeyboardLayout::SHIFT, (4, 2)), Some('g'));
+    /// assert_eq!(KeyboardLayout::named("qwertz"), None);
+    /// ```
+    pub fn named(name: &str) -> Option<Self> {
+        let with_symbols = |layout: KeyboardLayout| {
+            layout.with_layer(SYMBOL_KEYS.iter().map(|row| row.iter().copied()))
+        };
+
+        match name {
+            "qwerty" => Some(with_symbols(
+                KeyboardLayout::from(crate::KEYS).with_layer(QWERTY_SHIFT),
+            )),
+            "dvorak" => Some(with_symbols(
+                KeyboardLayout::from(DVORAK).with_layer(DVORAK_SHIFT),
+            )),
+            "azerty" => Some(with_symbols(
+                KeyboardLayout::from(AZERTY).with_layer(AZERTY_SHIFT),
+            )),
+            "colemak" => Some(with_symbols(
+                KeyboardLayout::from(COLEMAK).with_layer(COLEMAK_SHIFT),
+            )),
+            "alphabetical" => {
+                Some(KeyboardLayout::from(ALPHABETICAL).with_layer(ALPHABETICAL_SHIFT))
+            }
+            "phone" => Some(PHONE.into()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EdgePolicy, Keyboard, KeyboardLayout, LayerState};
+
+    fn assert_round_trip(name: &str, text: &str) {
+        let layout = KeyboardLayout::named(name).unwrap();
+        let mut keyboard = Keyboard {
+            keyboard_layout: layout,
+            position: (0, 0),
+            edge_policy: EdgePolicy::Error,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let instructions = keyboard.generate_instructions(text);
+
+        keyboard.run(&instructions).unwrap();
+        assert_eq!(keyboard.to_string(), text, "{}", name);
+    }
+
+    #[test]
+    fn test_every_named_layout_exists() {
+        for name in KeyboardLayout::NAMES {
+            assert!(KeyboardLayout::named(name).is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_should_round_trip_every_key_on_every_named_layout() {
+        for name in KeyboardLayout::NAMES {
+            let layout = KeyboardLayout::named(name).unwrap();
+            let keys: String = (0..layout.layer_count())
+                .flat_map(|layer| layout.rows_on(layer).flatten().flatten())
+                .collect();
+            let reversed: String = keys.chars().rev().collect();
+
+            assert_round_trip(name, &format!("{} {}\n{}", keys, reversed, keys));
+        }
+    }
+
+    #[test]
+    fn test_should_round_trip_words_on_named_layouts() {
+        assert_round_trip("qwerty", "Hello World");
+        assert_round_trip("dvorak", "The quick brown fox");
+        assert_round_trip("azerty", "Bonjour, le monde !");
+        assert_round_trip("colemak", "jumps over 1 LAZY dog?");
+        assert_round_trip("alphabetical", "Netflix & chill");
+        assert_round_trip("phone", "0800 *123#");
+    }
+}
@@ -1,168 +1,622 @@
 use std::{
-    collections::HashMap,
-    fmt,
-    ops::{Add, Sub},
+    borrow::BorrowMut,
+    error, fmt,
+    io::{self, BufRead},
+    str::FromStr,
 };
 
-use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::digit1,
-    combinator::{map, opt},
-    sequence::{tuple, preceded},
-    IResult,
+mod cost;
+mod generate;
+mod instruction;
+mod layout;
+mod optimize;
+mod output;
+mod render;
+mod stats;
+mod trace;
+mod validate;
+
+pub use cost::{CostModel, Keystrokes, Manhattan, TokenCount};
+pub use generate::{GenerateOptions, Generated, Untypeable, UntypeablePolicy};
+pub use instruction::{
+    parse_instructions, read_instructions, Instruction, ParseError, ParseErrorKind, ParseMode,
+    Program, ReadInstructions, Spanned,
 };
+pub use layout::{
+    KeyboardLayout, LayoutError, LayoutFormat, Location, ALPHABETICAL, AZERTY, COLEMAK, DVORAK,
+    PHONE,
+};
+pub use output::{CountingSink, NullSink, OutputSink, WriteSink};
+pub use stats::Stats;
+pub use trace::{Event, Trace};
+pub use validate::{Finding, FindingKind, Severity};
 
-type Position = (usize, usize);
-pub type KeyboardLayout = [[char; 10]; 4];
+pub type Position = (usize, usize);
 
-pub const KEYS: KeyboardLayout = [
+pub const KEYS: [[char; 10]; 4] = [
     ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'],
     ['Q', 'W', 'E', 'R', 'T', 'Y', 'U', 'I', 'O', 'P'],
     ['A', 'S', 'D', 'F', 'G', 'H', 'J', 'K', 'L', ';'],
     ['Z', 'X', 'C', 'V', 'B', 'N', 'M', ',', '.', '?'],
 ];
 
-enum Instruction {
-    Left(usize),
-    Up(usize),
-    Right(usize),
-    Down(usize),
-    Space,
-    NewLine,
-    Select,
-    Unknown,
+/// What happens when a move would take the cursor past the edge of the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgePolicy {
+    /// Wrap around to the opposite edge, as if the keyboard were a torus.
+    #[default]
+    Wrap,
+    /// Stop at the edge.
+    Clamp,
+    /// Stop running with [`KeyboardError::OutOfBounds`].
+    Error,
+    /// Wrap like a typewriter: running off the end of a row continues at the start of the
+    /// next row, and running off the start continues at the end of the previous row. The last
+    /// row continues on the first. Vertical moves wrap as with [`EdgePolicy::Wrap`].
+    RowWrap,
+}
+
+impl EdgePolicy {
+    fn step(
+        self,
+        (x, y): Position,
+        direction: Direction,
+        count: usize,
+        (width, height): (usize, usize),
+    ) -> Option<Position> {
+        match direction {
+            Direction::Left | Direction::Right if self == EdgePolicy::RowWrap => {
+                let index = self.along(y * width + x, count, width * height, direction)?;
+                Some((index % width, index / width))
+            }
+            Direction::Left | Direction::Right => {
+                Some((self.along(x, count, width, direction)?, y))
+            }
+            Direction::Up | Direction::Down => Some((x, self.along(y, count, height, direction)?)),
+        }
+    }
+
+    /// Whether a move passes an edge and comes back around on the other side, or for
+    /// [`EdgePolicy::RowWrap`] carries on along another row.
+    fn wraps(
+        self,
+        (x, y): Position,
+        direction: Direction,
+        count: usize,
+        (width, height): (usize, usize),
+    ) -> bool {
+        let crosses = match direction {
+            Direction::Left => count > x,
+            Direction::Up => count > y,
+            Direction::Right => x.saturating_add(count) >= width,
+            Direction::Down => y.saturating_add(count) >= height,
+        };
+
+        crosses && matches!(self, EdgePolicy::Wrap | EdgePolicy::RowWrap)
+    }
+
+    fn along(self, value: usize, count: usize, len: usize, direction: Direction) -> Option<usize> {
+        let forward = matches!(direction, Direction::Right | Direction::Down);
+
+        match self {
+            EdgePolicy::Wrap | EdgePolicy::RowWrap if forward => Some((value + count % len) % len),
+            EdgePolicy::Wrap | EdgePolicy::RowWrap => Some((value + len - count % len) % len),
+            EdgePolicy::Clamp if forward => Some(value.saturating_add(count).min(len - 1)),
+            EdgePolicy::Clamp => Some(value.saturating_sub(count)),
+            EdgePolicy::Error if forward => value.checked_add(count).filter(|&v| v < len),
+            EdgePolicy::Error => value.checked_sub(count),
+        }
+    }
 }
 
-impl From<&str> for Instruction {
-    fn from(s: &str) -> Self {
-        match parse_instruction(s) {
-            Ok((_, inst)) => inst,
-            Err(_) => Instruction::Unknown,
+impl FromStr for EdgePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(EdgePolicy::Wrap),
+            "clamp" => Ok(EdgePolicy::Clamp),
+            "error" => Ok(EdgePolicy::Error),
+            "row-wrap" => Ok(EdgePolicy::RowWrap),
+            _ => Err(format!(
+                "unknown edge policy `{}`, expected one of: wrap, clamp, error, row-wrap",
+                s
+            )),
         }
     }
 }
 
-fn parse_instruction(input: &str) -> IResult<&str, Instruction> {
-    let (input, (instruction, count)) = tuple((
-        alt((
-            tag("L"),
-            tag("R"),
-            tag("U"),
-            tag("D"),
-            tag("S"),
-            tag("_"),
-            tag("N"),
-        )),
-        opt(preceded(
-            tag(":"),
-            map(digit1, |count: &str| count.parse::<usize>().unwrap()),
-        )),
-    ))(input)?;
-
-    let count = count.unwrap_or(1);
-
-    let instruction = match instruction {
-        "L" => Instruction::Left(count),
-        "R" => Instruction::Right(count),
-        "U" => Instruction::Up(count),
-        "D" => Instruction::Down(count),
-        "_" => Instruction::Space,
-        "N" => Instruction::NewLine,
-        "S" => Instruction::Select,
-        _ => unreachable!(),
-    };
-
-    Ok((input, instruction))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Left,
+    Up,
+    Right,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyboardError {
+    /// The instruction at `index` would have moved the cursor off the keyboard from `position`.
+    OutOfBounds { index: usize, position: Position },
+    /// The instructions could not be parsed.
+    Parse(ParseError),
+    /// The text to generate instructions for has a character that is not on the layout.
+    Untypeable(Untypeable),
+    /// Instructions cannot be generated from `position`, as it is not on the layout.
+    OffLayout(Position),
+    /// The instructions could not be read.
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
 }
 
-pub struct Keyboard<'a> {
+impl fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyboardError::OutOfBounds { index, position } => write!(
+                f,
+                "instruction {} moves off the keyboard from ({}, {})",
+                index, position.0, position.1
+            ),
+            KeyboardError::Parse(err) => write!(f, "{}", err),
+            KeyboardError::Untypeable(untypeable) => write!(f, "{}", untypeable),
+            KeyboardError::OffLayout(position) => {
+                write!(
+                    f,
+                    "position ({}, {}) is off the layout",
+                    position.0, position.1
+                )
+            }
+            KeyboardError::Io { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for KeyboardError {}
+
+impl From<ParseError> for KeyboardError {
+    fn from(err: ParseError) -> Self {
+        KeyboardError::Parse(err)
+    }
+}
+
+impl From<io::Error> for KeyboardError {
+    fn from(err: io::Error) -> Self {
+        KeyboardError::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Which layer of the layout the keyboard selects keys from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LayerState {
+    /// The layer locked in by `M`, the base layer to begin with.
+    pub locked: usize,
+    /// The layer picked by `^` for the next `S` only.
+    pub one_shot: Option<usize>,
+}
+
+impl LayerState {
+    /// The layer the next `S` selects from.
+    pub fn active(&self) -> usize {
+        self.one_shot.unwrap_or(self.locked)
+    }
+}
+
+/// A keyboard with a cursor, and the keys it has selected so far.
+///
+/// The selected keys go into `selected_keys`, which is usually a `Vec<char>` the keyboard owns,
+/// as made by [`Keyboard::builder`], or a `&mut Vec<char>` lent to it. Any [`OutputSink`] will
+/// do for running instructions, such as a [`WriteSink`] to print keys as they are selected.
+#[derive(Debug, Clone)]
+pub struct Keyboard<O = Vec<char>> {
     pub keyboard_layout: KeyboardLayout,
     pub position: Position,
-    pub selected_keys: &'a mut Vec<char>,
+    pub edge_policy: EdgePolicy,
+    pub layers: LayerState,
+    pub selected_keys: O,
 }
 
-impl<'a> fmt::Display for Keyboard<'a> {
+impl<O: BorrowMut<Vec<char>>> fmt::Display for Keyboard<O> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s: String = self.selected_keys.iter().collect();
+        let s: String = self.output().iter().collect();
         write!(f, "{}", s)
     }
 }
 
-impl<'a> Keyboard<'a> {
+impl Keyboard {
+    /// Starts building a keyboard that owns its selected keys, starting in the top left corner
+    /// and wrapping around the edges.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{EdgePolicy, Keyboard, KEYS};
+    ///
+    /// let mut keyboard = Keyboard::builder(KEYS.into())
+    ///     .position((4, 2))
+    ///     .edge_policy(EdgePolicy::Clamp)
+    ///     .build();
+    ///
+    /// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
+    /// assert_eq!(keyboard.output(), ['H', 'E', 'L', 'L', 'O']);
+    /// ```
+    pub fn builder(keyboard_layout: KeyboardLayout) -> KeyboardBuilder {
+        KeyboardBuilder {
+            keyboard: Keyboard {
+                keyboard_layout,
+                position: (0, 0),
+                edge_policy: EdgePolicy::default(),
+                layers: LayerState::default(),
+                selected_keys: vec![],
+            },
+        }
+    }
+}
+
+/// Builds a [`Keyboard`] that owns its selected keys. See [`Keyboard::builder`].
+#[derive(Debug, Clone)]
+pub struct KeyboardBuilder {
+    keyboard: Keyboard,
+}
+
+impl KeyboardBuilder {
+    /// Where the cursor starts.
+    pub fn position(mut self, position: Position) -> Self {
+        self.keyboard.position = position;
+        self
+    }
+
+    pub fn edge_policy(mut self, edge_policy: EdgePolicy) -> Self {
+        self.keyboard.edge_policy = edge_policy;
+        self
+    }
+
+    /// The layers to start with, the base layer otherwise.
+    pub fn layers(mut self, layers: LayerState) -> Self {
+        self.keyboard.layers = layers;
+        self
+    }
+
+    pub fn build(self) -> Keyboard {
+        self.keyboard
+    }
+
+    /// Builds a keyboard that puts the keys it selects into `output` instead.
+    pub fn build_with<O: OutputSink>(self, output: O) -> Keyboard<O> {
+        let Keyboard {
+            keyboard_layout,
+            position,
+            edge_policy,
+            layers,
+            selected_keys: _,
+        } = self.keyboard;
+
+        Keyboard {
+            keyboard_layout,
+            position,
+            edge_policy,
+            layers,
+            selected_keys: output,
+        }
+    }
+}
+
+impl<O: BorrowMut<Vec<char>>> Keyboard<O> {
+    /// The keys selected so far.
+    pub fn output(&self) -> &[char] {
+        self.selected_keys.borrow()
+    }
+
+    /// Takes the keys selected so far, leaving none behind.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Keyboard, KEYS};
+    ///
+    /// let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+    ///
+    /// keyboard.run("R,S,U,L:3,S").unwrap();
+    /// assert_eq!(keyboard.take_output(), vec!['H', 'E']);
+    /// keyboard.run("D,R:6,S,S,U,S").unwrap();
+    /// assert_eq!(keyboard.take_output(), vec!['L', 'L', 'O']);
+    /// assert!(keyboard.output().is_empty());
+    /// ```
+    pub fn take_output(&mut self) -> Vec<char> {
+        std::mem::take(self.selected_keys.borrow_mut())
+    }
+
+    /// Clears the selected keys and layers and moves the cursor to `position`, ready to run
+    /// instructions from the start again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Keyboard, KeyboardLayout};
+    ///
+    /// let mut keyboard = Keyboard::builder(KeyboardLayout::named("qwerty").unwrap())
+    ///     .position((4, 2))
+    ///     .build();
+    ///
+    /// keyboard.run("M,R,S").unwrap();
+    /// keyboard.reset((4, 2));
+    /// keyboard.run("M,R,S").unwrap();
+    /// assert_eq!(keyboard.to_string(), "h");
+    /// ```
+    pub fn reset(&mut self, position: Position) {
+        self.clear();
+        self.position = position;
+        self.layers = LayerState::default();
+    }
+
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut keyboard = keyboard_madness::Keyboard {
+    /// #    keyboard_layout: keyboard_madness::KEYS.into(),
+    /// #    position: (4, 2),
+    /// #    edge_policy: keyboard_madness::EdgePolicy::Wrap,
+    /// #    layers: keyboard_madness::LayerState::default(),
+    /// #    selected_keys: &mut vec![],
+    /// # };
+    /// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
+    /// assert_eq!(keyboard.to_string(), "HELLO");
+    /// keyboard.clear();
+    /// assert_eq!(keyboard.to_string(), "");
+    /// ```
+    pub fn clear(&mut self) {
+        self.selected_keys.borrow_mut().truncate(0)
+    }
+}
+
+impl<O: OutputSink> Keyboard<O> {
     pub fn update_position(&mut self, position: Position) {
-        self.position = (position.0 % 10, position.1 % 4);
+        let (width, height) = self.keyboard_layout.size();
+        self.position = (position.0 % width, position.1 % height);
     }
 
     fn selected_key(&mut self, key: char) {
         self.selected_keys.push(key);
     }
 
-    fn execute(&mut self, instruction: Instruction) {
-        let (x, y) = self.position;
+    /// Moves the cursor, returning whether it wrapped around an edge on the way.
+    fn move_cursor(
+        &mut self,
+        index: usize,
+        direction: Direction,
+        count: usize,
+    ) -> Result<bool, KeyboardError> {
+        let size = self.keyboard_layout.size();
+
+        match self.edge_policy.step(self.position, direction, count, size) {
+            Some(position) => {
+                let wrapped = self
+                    .edge_policy
+                    .wraps(self.position, direction, count, size);
+                self.position = position;
+                Ok(wrapped)
+            }
+            None => Err(KeyboardError::OutOfBounds {
+                index,
+                position: self.position,
+            }),
+        }
+    }
 
-        match instruction {
-            Instruction::Left(count) => self.update_position((x.sub(count), y)),
-            Instruction::Up(count) => self.update_position((x, y.sub(count))),
-            Instruction::Right(count) => self.update_position((x.add(count), y)),
-            Instruction::Down(count) => self.update_position((x, y.add(count))),
-            Instruction::Space => self.selected_key(' '),
-            Instruction::NewLine => self.selected_key('\n'),
-            Instruction::Select => self.selected_key(self.keyboard_layout[y][x]),
-            Instruction::Unknown => {}
+    /// Runs a single instruction, the `index`th of its program, and describes what it did.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Instruction, Keyboard, KEYS};
+    ///
+    /// let mut keyboard = Keyboard::builder(KEYS.into()).position((9, 2)).build();
+    ///
+    /// let event = keyboard.execute(0, &Instruction::Right(2)).unwrap();
+    /// assert_eq!((event.before, event.after, event.wrapped), ((9, 2), (1, 2), true));
+    /// assert_eq!(event.key, Some('S'));
+    ///
+    /// let event = keyboard.execute(1, &Instruction::Select).unwrap();
+    /// assert_eq!(event.emitted, Some('S'));
+    /// ```
+    pub fn execute(
+        &mut self,
+        index: usize,
+        instruction: &Instruction,
+    ) -> Result<Event, KeyboardError> {
+        let before = self.position;
+        let mut wrapped = false;
+        let emitted = match *instruction {
+            Instruction::Left(count) => {
+                wrapped = self.move_cursor(index, Direction::Left, count)?;
+                None
+            }
+            Instruction::Up(count) => {
+                wrapped = self.move_cursor(index, Direction::Up, count)?;
+                None
+            }
+            Instruction::Right(count) => {
+                wrapped = self.move_cursor(index, Direction::Right, count)?;
+                None
+            }
+            Instruction::Down(count) => {
+                wrapped = self.move_cursor(index, Direction::Down, count)?;
+                None
+            }
+            Instruction::Space => Some(' '),
+            Instruction::NewLine => Some('\n'),
+            Instruction::Select => {
+                let layer = self.layers.active();
+                self.layers.one_shot = None;
+                self.keyboard_layout.get_on(layer, self.position)
+            }
+            Instruction::Shift(layer) => {
+                self.layers.one_shot = Some(layer);
+                None
+            }
+            Instruction::Lock(layer) => {
+                self.layers.locked = if layer == self.layers.locked {
+                    KeyboardLayout::BASE
+                } else {
+                    layer
+                };
+                None
+            }
+            Instruction::Unknown(_) => None,
+        };
+        if let Some(key) = emitted {
+            self.selected_key(key);
         }
+
+        Ok(Event {
+            index,
+            instruction: instruction.clone(),
+            before,
+            after: self.position,
+            key: self
+                .keyboard_layout
+                .get_on(self.layers.active(), self.position),
+            emitted,
+            wrapped,
+            layers: self.layers,
+        })
     }
 
+    /// Runs a program an instruction at a time, as an iterator of what each one did. The
+    /// iterator stops after the first error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Keyboard, Program, KEYS};
+    ///
+    /// let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+    /// let program: Program = "R,S,U,L:3,S,D,L:4,S,S,U,S".parse().unwrap();
+    ///
+    /// let wrapped: Vec<_> = keyboard
+    ///     .trace(&program)
+    ///     .map(|event| event.unwrap())
+    ///     .filter(|event| event.wrapped)
+    ///     .map(|event| event.index)
+    ///     .collect();
+    ///
+    /// assert_eq!(wrapped, vec![6]);
+    /// assert_eq!(keyboard.to_string(), "HELLO");
+    /// ```
+    pub fn trace<'a>(&'a mut self, program: &'a Program) -> Trace<'a, O> {
+        Trace::new(self, program)
+    }
+
+    /// Runs the comma separated instructions, stopping at the first move that the keyboard's
+    /// [`EdgePolicy`] rejects.
+    ///
     /// # Examples
     ///
     /// ```
     /// # let mut keyboard = keyboard_madness::Keyboard {
-    /// #    keyboard_layout: keyboard_madness::KEYS,
+    /// #    keyboard_layout: keyboard_madness::KEYS.into(),
     /// #    position: (4, 2),
+    /// #    edge_policy: keyboard_madness::EdgePolicy::Wrap,
+    /// #    layers: keyboard_madness::LayerState::default(),
     /// #    selected_keys: &mut vec![],
     /// # };
-    /// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S");
+    /// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
     /// assert_eq!(keyboard.to_string(), "HELLO");
     /// ```
-    pub fn run(&mut self, instructions: &str) {
-        instructions
-            .split(',')
-            .map(|i| i.into())
-            .for_each(|instruction| self.execute(instruction));
+    pub fn run(&mut self, instructions: &str) -> Result<(), KeyboardError> {
+        self.run_with_mode(instructions, ParseMode::Lenient)
     }
 
+    /// Runs the comma separated instructions like [`Keyboard::run`], but only after checking
+    /// that every token is a valid instruction. Nothing is run if a token is not.
+    ///
     /// # Examples
     ///
     /// ```
     /// # let mut keyboard = keyboard_madness::Keyboard {
-    /// #    keyboard_layout: keyboard_madness::KEYS,
+    /// #    keyboard_layout: keyboard_madness::KEYS.into(),
     /// #    position: (4, 2),
+    /// #    edge_policy: keyboard_madness::EdgePolicy::Wrap,
+    /// #    layers: keyboard_madness::LayerState::default(),
     /// #    selected_keys: &mut vec![],
     /// # };
-    /// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S");
-    /// assert_eq!(keyboard.to_string(), "HELLO");
-    /// keyboard.clear();
+    /// let err = keyboard.run_strict("R,S,U,L:3,SX").unwrap_err();
+    /// assert_eq!(err.to_string(), "instruction 4 at bytes 10..12: unexpected input after instruction");
     /// assert_eq!(keyboard.to_string(), "");
     /// ```
-    pub fn clear(&mut self) {
-        self.selected_keys.truncate(0)
+    pub fn run_strict(&mut self, instructions: &str) -> Result<(), KeyboardError> {
+        self.run_with_mode(instructions, ParseMode::Strict)
     }
 
-    fn find_position(&mut self, key: char) -> Option<Position> {
-        for (y, row) in self.keyboard_layout.iter().enumerate() {
-            if let Some(x) = row.iter().position(|&ch| ch == key) {
-                return Some((x, y));
-            }
-        }
-        None
+    fn run_with_mode(&mut self, instructions: &str, mode: ParseMode) -> Result<(), KeyboardError> {
+        self.run_program(&Program::parse(instructions, mode)?)
+    }
+
+    /// Runs a parsed or built up [`Program`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Instruction, Program};
+    ///
+    /// # let mut keyboard = keyboard_madness::Keyboard {
+    /// #    keyboard_layout: keyboard_madness::KEYS.into(),
+    /// #    position: (4, 2),
+    /// #    edge_policy: keyboard_madness::EdgePolicy::Wrap,
+    /// #    layers: keyboard_madness::LayerState::default(),
+    /// #    selected_keys: &mut vec![],
+    /// # };
+    /// let program: Program = vec![Instruction::Right(1), Instruction::Select].into();
+    ///
+    /// keyboard.run_program(&program).unwrap();
+    /// assert_eq!(keyboard.to_string(), "H");
+    /// ```
+    pub fn run_program(&mut self, program: &Program) -> Result<(), KeyboardError> {
+        program
+            .instructions()
+            .enumerate()
+            .try_for_each(|(index, instruction)| self.execute(index, instruction).map(|_| ()))
+    }
+
+    /// Runs comma separated instructions as they are read from `reader`, without reading the
+    /// whole input first, so there is no limit on how long the input can be.
+    ///
+    /// Unlike [`Keyboard::run_strict`], [`ParseMode::Strict`] runs the instructions before an
+    /// invalid one, since it is not known to be invalid until they have run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::BufReader;
+    ///
+    /// use keyboard_madness::{Keyboard, ParseMode, KEYS};
+    ///
+    /// let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+    /// let file = "R,S,U,L:3,S,\nD,R:6,S,S,U,S\n".as_bytes();
+    ///
+    /// keyboard.run_reader(BufReader::new(file), ParseMode::Strict).unwrap();
+    /// assert_eq!(keyboard.to_string(), "HELLO");
+    /// ```
+    pub fn run_reader<R: BufRead>(
+        &mut self,
+        reader: R,
+        mode: ParseMode,
+    ) -> Result<(), KeyboardError> {
+        read_instructions(reader, mode)
+            .enumerate()
+            .try_for_each(|(index, step)| self.execute(index, &step?.instruction).map(|_| ()))
     }
 
-    /// Generates a series of instructions to produce the given text using the custom keyboard.
+    /// Generates a series of instructions to produce the given text using the custom keyboard,
+    /// pressing as few buttons as possible. See [`Keyboard::generate_instructions_with`].
     ///
     /// # Arguments
     ///
     /// * `text` - The input text to generate instructions for.
-    /// * `keyboard_layout` - The custom keyboard layout.
     ///
     /// # Returns
     ///
@@ -171,83 +625,220 @@ impl<'a> Keyboard<'a> {
     /// # Examples
     ///
     /// ```
-    /// let text = "HELLO";
+    /// let text = "Hello, World!";
     ///
     /// let mut keyboard = keyboard_madness::Keyboard {
-    ///     keyboard_layout: keyboard_madness::KEYS,
+    ///     keyboard_layout: keyboard_madness::KeyboardLayout::named("qwerty").unwrap(),
     ///     position: (4, 2),
+    ///     edge_policy: keyboard_madness::EdgePolicy::Wrap,
+    ///     layers: keyboard_madness::LayerState::default(),
     ///     selected_keys: &mut vec![],
     /// };
     ///
     /// let instructions = keyboard.generate_instructions(text);
     ///
-    /// keyboard.run(&instructions);
+    /// keyboard.run(&instructions).unwrap();
     /// assert_eq!(keyboard.to_string(), text);
     /// ```
     pub fn generate_instructions(&mut self, text: &str) -> String {
-        let mut instructions = String::new();
-        let mut position = self.position; // Starting position
-
-        let mut char_positions = HashMap::new();
-        for ch in text.chars() {
-            if let std::collections::hash_map::Entry::Vacant(e) = char_positions.entry(ch) {
-                let pos = self.find_position(ch);
-                if let Some(pos) = pos {
-                    e.insert(pos);
-                }
-            }
+        self.generate_instructions_with(text, &Keystrokes)
+    }
+
+    /// Generates the cheapest instructions under `cost_model` to produce the given text, written
+    /// in the canonical form of [`Program`].
+    ///
+    /// Moves take the keyboard's [`EdgePolicy`] into account, so under [`EdgePolicy::Wrap`] and
+    /// [`EdgePolicy::RowWrap`] they may go the other way around the keyboard. Moves may pass over
+    /// empty cells on the way, but only ever stop to select a key. Keys on other layers are
+    /// selected by shifting to their layer with `^` or locking it with `M`, whichever is cheaper
+    /// over the whole text. Between programs that cost the same, the one with the fewest
+    /// instructions wins.
+    ///
+    /// A position off the layout is first brought back onto it, as [`Keyboard::update_position`]
+    /// does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Instruction, Keystrokes, TokenCount};
+    ///
+    /// let mut keyboard = keyboard_madness::Keyboard {
+    ///     keyboard_layout: keyboard_madness::KEYS.into(),
+    ///     position: (0, 0),
+    ///     edge_policy: keyboard_madness::EdgePolicy::Clamp,
+    ///     layers: keyboard_madness::LayerState::default(),
+    ///     selected_keys: &mut vec![],
+    /// };
+    ///
+    /// assert_eq!(keyboard.generate_instructions_with("8", &Keystrokes), "R:7,S");
+    /// assert_eq!(keyboard.generate_instructions_with("0", &TokenCount), "R:9,S");
+    ///
+    /// let vertical = |instruction: &Instruction| match *instruction {
+    ///     Instruction::Up(count) | Instruction::Down(count) => 10 * count,
+    ///     _ => 1,
+    /// };
+    /// assert_eq!(keyboard.generate_instructions_with("QA", &vertical), "D,S,D,S");
+    /// ```
+    pub fn generate_instructions_with(&mut self, text: &str, cost_model: &dyn CostModel) -> String {
+        let options = GenerateOptions {
+            cost_model,
+            ..GenerateOptions::default()
+        };
+
+        self.update_position(self.position);
+        self.generate(text, &options)
+            .expect("skipping untypeable characters from a position on the layout never fails")
+            .program
+            .to_string()
+    }
+
+    /// Generates the cheapest instructions to produce the given text, like
+    /// [`Keyboard::generate_instructions_with`], reporting the characters that are not on the
+    /// layout and dealing with them as `options` says.
+    ///
+    /// The result also has where the cursor and layers end up after running the instructions.
+    /// With [`GenerateOptions::advance`] the keyboard is left there too, so that a long text can
+    /// be generated a piece at a time and the pieces run one after the other.
+    ///
+    /// Fails with [`KeyboardError::OffLayout`] when the cursor is not on the layout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// use keyboard_madness::{GenerateOptions, KeyboardError, Untypeable, UntypeablePolicy};
+    ///
+    /// # let mut keyboard = keyboard_madness::Keyboard {
+    /// #    keyboard_layout: keyboard_madness::KEYS.into(),
+    /// #    position: (4, 2),
+    /// #    edge_policy: keyboard_madness::EdgePolicy::Wrap,
+    /// #    layers: keyboard_madness::LayerState::default(),
+    /// #    selected_keys: &mut vec![],
+    /// # };
+    /// let generated = keyboard.generate("HELLO!", &GenerateOptions::default()).unwrap();
+    ///
+    /// assert_eq!(generated.program.to_string(), "R,S,L:3,U,S,L:4,D,S,S,U,S");
+    /// assert_eq!(
+    ///     generated.untypeable,
+    ///     vec![Untypeable { index: 5, key: '!', substitute: None }]
+    /// );
+    ///
+    /// let options = GenerateOptions {
+    ///     untypeable: UntypeablePolicy::Substitute(HashMap::from([('!', '.')])),
+    ///     ..GenerateOptions::default()
+    /// };
+    /// let generated = keyboard.generate("HELLO!", &options).unwrap();
+    ///
+    /// assert_eq!(generated.untypeable[0].substitute, Some('.'));
+    /// keyboard.run_program(&generated.program).unwrap();
+    /// assert_eq!(keyboard.to_string(), "HELLO.");
+    ///
+    /// let options = GenerateOptions {
+    ///     untypeable: UntypeablePolicy::Fail,
+    ///     ..GenerateOptions::default()
+    /// };
+    /// assert_eq!(
+    ///     keyboard.generate("HELLO!", &options).unwrap_err().to_string(),
+    ///     "character 5 '!' is not on the layout"
+    /// );
+    /// ```
+    pub fn generate(
+        &mut self,
+        text: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generated, KeyboardError> {
+        let (width, height) = self.keyboard_layout.size();
+        if self.position.0 >= width || self.position.1 >= height {
+            return Err(KeyboardError::OffLayout(self.position));
         }
 
-        for ch in text.chars() {
-            if ch == ' ' {
-                instructions.push_str("_,");
-                continue;
-            }
-            if ch == '\n' {
-                instructions.push_str("N,");
+        let mut typed = String::new();
+        let mut untypeable = vec![];
+
+        for (index, key) in text.chars().enumerate() {
+            if generate::typeable(&self.keyboard_layout, key) {
+                typed.push(key);
                 continue;
             }
 
-            if let Some(target) = char_positions.get(&ch) {
-                let dx = target.0 as i32 - position.0 as i32;
-                let dy = target.1 as i32 - position.1 as i32;
-
-                match dx.cmp(&0) {
-                    std::cmp::Ordering::Greater => instructions.push_str(&format!("R:{},", dx)),
-                    std::cmp::Ordering::Less => instructions.push_str(&format!("L:{},", dx.abs())),
-                    _ => {}
+            let substitute = match &options.untypeable {
+                UntypeablePolicy::Fail => {
+                    return Err(KeyboardError::Untypeable(Untypeable {
+                        index,
+                        key,
+                        substitute: None,
+                    }))
                 }
+                UntypeablePolicy::Skip => None,
+                UntypeablePolicy::Substitute(substitutes) => substitutes
+                    .get(&key)
+                    .copied()
+                    .filter(|&substitute| generate::typeable(&self.keyboard_layout, substitute)),
+            };
+            typed.extend(substitute);
+            untypeable.push(Untypeable {
+                index,
+                key,
+                substitute,
+            });
+        }
 
-                match dy.cmp(&0) {
-                    std::cmp::Ordering::Greater => instructions.push_str(&format!("D:{},", dy)),
-                    std::cmp::Ordering::Less => instructions.push_str(&format!("U:{},", dy.abs())),
-                    _ => {}
-                }
+        let (program, position, layers) =
+            generate::Planner::new(&self.keyboard_layout, self.edge_policy, options.cost_model)
+                .plan(self.position, self.layers, &typed);
 
-                instructions.push_str("S,");
-                position = *target;
-            }
+        if options.advance {
+            self.position = position;
+            self.layers = layers;
         }
 
-        instructions.pop(); // Remove the trailing comma
-        instructions
+        Ok(Generated {
+            program,
+            untypeable,
+            position,
+            layers,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     // this brings everything from parent's scope into this scope
     use super::*;
 
+    #[test]
+    fn test_should_own_its_selected_keys() {
+        fn hello() -> Keyboard {
+            let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+            keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
+            keyboard
+        }
+
+        let mut keyboard = std::thread::spawn(hello).join().unwrap();
+
+        assert_eq!(keyboard.to_string(), "HELLO");
+        assert_eq!(keyboard.position, (8, 1));
+        assert_eq!(keyboard.take_output(), "HELLO".chars().collect::<Vec<_>>());
+
+        keyboard.reset((4, 2));
+        keyboard.run("R,S").unwrap();
+        assert_eq!(keyboard.to_string(), "H");
+    }
+
     #[test]
     fn test_should_select_the_starting_points_key() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("S");
+        keyboard.run("S").unwrap();
 
         assert_eq!(keyboard.to_string(), "G");
     }
@@ -255,12 +846,14 @@ mod tests {
     #[test]
     fn test_should_select_the_first_letter_to_the_left_of_the_starting_point() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("L,S");
+        keyboard.run("L,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "F");
     }
@@ -268,12 +861,14 @@ mod tests {
     #[test]
     fn test_should_select_the_third_letter_to_the_left_of_the_starting_point() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("L:3,S");
+        keyboard.run("L:3,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "S");
     }
@@ -281,12 +876,14 @@ mod tests {
     #[test]
     fn test_should_select_the_first_letter_to_the_right_of_the_starting_point() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("R,S");
+        keyboard.run("R,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "H");
     }
@@ -294,12 +891,14 @@ mod tests {
     #[test]
     fn test_should_select_the_third_letter_to_the_right_of_the_starting_point() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("R:3,S");
+        keyboard.run("R:3,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "K");
     }
@@ -307,12 +906,14 @@ mod tests {
     #[test]
     fn test_should_select_the_letter_above_of_the_starting_point() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("U,S");
+        keyboard.run("U,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "T");
     }
@@ -320,12 +921,14 @@ mod tests {
     #[test]
     fn test_should_select_letter_below_of_the_starting_point() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("D,S");
+        keyboard.run("D,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "B");
     }
@@ -333,12 +936,14 @@ mod tests {
     #[test]
     fn test_should_add_a_space_into_the_selected_keys() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("S,_,S");
+        keyboard.run("S,_,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "G G");
     }
@@ -346,12 +951,14 @@ mod tests {
     #[test]
     fn test_should_add_a_new_line_into_the_selected_keys() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("S,N,S");
+        keyboard.run("S,N,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "G\nG");
     }
@@ -359,12 +966,14 @@ mod tests {
     #[test]
     fn test_should_ignore_any_unknown_instructions() {
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("S,Testing,Testing,Testing,S");
+        keyboard.run("S,Testing,Testing,Testing,S").unwrap();
 
         assert_eq!(keyboard.to_string(), "GG");
     }
@@ -373,41 +982,561 @@ mod tests {
     fn test_should_select_the_correct_keys() {
         let starting_position: Position = (4, 2);
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: starting_position,
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
 
-        keyboard.run("R,S,R:2,U,S");
+        keyboard.run("R,S,R:2,U,S").unwrap();
         assert_eq!(keyboard.to_string(), "HI");
         keyboard.clear();
         keyboard.update_position(starting_position);
 
-        keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S");
+        keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
         assert_eq!(keyboard.to_string(), "HELLO");
         keyboard.clear();
         keyboard.update_position(starting_position);
 
-        keyboard.run("L:3,S,U,R:5,S,R:3,S,D:2,S");
+        keyboard.run("L:3,S,U,R:5,S,R:3,S,D:2,S").unwrap();
         assert_eq!(keyboard.to_string(), "SUP?");
         keyboard.clear();
         keyboard.update_position(starting_position);
 
-        keyboard.run("R,S,L,U,S,S,R:5,S,_,U:1,L:6,S,R:6,S,L:6,S");
+        keyboard
+            .run("R,S,L,U,S,S,R:5,S,_,U:1,L:6,S,R:6,S,L:6,S")
+            .unwrap();
         assert_eq!(keyboard.to_string(), "HTTP 404");
     }
 
+    #[test]
+    fn test_should_ignore_counts_that_overflow() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("S,R:99999999999999999999,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "GG");
+    }
+
+    #[test]
+    fn test_should_run_nothing_when_strict_and_a_token_is_invalid() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        assert_eq!(
+            keyboard.run_strict("S,R,S,Testing"),
+            Err(KeyboardError::Parse(ParseError {
+                kind: ParseErrorKind::Unknown,
+                index: 3,
+                span: 6..13
+            }))
+        );
+        assert_eq!(keyboard.to_string(), "");
+        assert_eq!(keyboard.position, (4, 2));
+
+        keyboard.run_strict("S, R, S").unwrap();
+        assert_eq!(keyboard.to_string(), "GH");
+    }
+
+    #[test]
+    fn test_should_wrap_around_the_edges() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("L:5,S,U:3,S,R:21,S,D:7,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), ";?ZA");
+    }
+
+    #[test]
+    fn test_should_clamp_at_the_edges() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Clamp,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("L:5,S,U:3,S,R:99,S,D:7,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "A10?");
+    }
+
+    #[test]
+    fn test_should_error_when_moving_off_the_edge() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Error,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        assert_eq!(
+            keyboard.run("S,L:4,S,L,S"),
+            Err(KeyboardError::OutOfBounds {
+                index: 3,
+                position: (0, 2)
+            })
+        );
+        assert_eq!(keyboard.to_string(), "GA");
+        assert_eq!(keyboard.position, (0, 2));
+    }
+
+    #[test]
+    fn test_should_wrap_onto_the_next_and_previous_rows() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::RowWrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("L:5,S,R:6,S,D,R:10,S,U,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "PH6N");
+    }
+
+    #[test]
+    fn test_generated_instructions_run_under_every_edge_policy() {
+        let text = "THE QUICK BROWN FOX\nJUMPS OVER 1,2,3?";
+
+        for edge_policy in [
+            EdgePolicy::Wrap,
+            EdgePolicy::Clamp,
+            EdgePolicy::Error,
+            EdgePolicy::RowWrap,
+        ] {
+            let mut keyboard = Keyboard {
+                keyboard_layout: KEYS.into(),
+                position: (4, 2),
+                edge_policy,
+                layers: LayerState::default(),
+                selected_keys: &mut vec![],
+            };
+            let instructions = keyboard.generate_instructions(text);
+
+            keyboard.run(&instructions).unwrap();
+            assert_eq!(keyboard.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_should_generate_the_shortest_moves_around_the_edges() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (0, 0),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        assert_eq!(keyboard.generate_instructions("0Z"), "L,S,R,U,S");
+
+        keyboard.edge_policy = EdgePolicy::RowWrap;
+        assert_eq!(keyboard.generate_instructions("0Z"), "L,D,S,R,D:2,S");
+
+        keyboard.edge_policy = EdgePolicy::Clamp;
+        assert_eq!(keyboard.generate_instructions("0Z"), "R:9,S,L:9,D:3,S");
+    }
+
+    #[test]
+    fn test_generated_instructions_are_never_longer_than_direct_moves() {
+        let texts = [
+            "THE QUICK BROWN FOX\nJUMPS OVER 1,2,3?",
+            "Keyboard Madness, \"version\" {2}\nrun & generate: [OK]?",
+            "0P1Q0Z9A",
+        ];
+        let moves = |instructions: &str| -> usize {
+            parse_instructions(instructions, ParseMode::Strict)
+                .unwrap()
+                .iter()
+                .map(|instruction| match *instruction {
+                    Instruction::Left(count)
+                    | Instruction::Up(count)
+                    | Instruction::Right(count)
+                    | Instruction::Down(count) => count,
+                    _ => 0,
+                })
+                .sum()
+        };
+
+        for name in ["qwerty", "alphabetical", "phone"] {
+            for text in texts {
+                let text: String = text
+                    .chars()
+                    .filter(|&ch| {
+                        ch == ' ' || KeyboardLayout::named(name).unwrap().find(ch).is_some()
+                    })
+                    .collect();
+                let generate = |edge_policy| {
+                    let mut keyboard = Keyboard {
+                        keyboard_layout: KeyboardLayout::named(name).unwrap(),
+                        position: (1, 1),
+                        edge_policy,
+                        layers: LayerState::default(),
+                        selected_keys: &mut vec![],
+                    };
+                    let instructions = keyboard.generate_instructions(&text);
+
+                    keyboard.run(&instructions).unwrap();
+                    assert_eq!(keyboard.to_string(), text);
+                    instructions
+                };
+                // Clamped moves never cross an edge, so they are the direct moves.
+                let direct = generate(EdgePolicy::Clamp);
+
+                for edge_policy in [EdgePolicy::Wrap, EdgePolicy::RowWrap] {
+                    let instructions = generate(edge_policy);
+
+                    assert!(
+                        instructions.len() <= direct.len(),
+                        "{}: {}",
+                        name,
+                        instructions
+                    );
+                    assert!(
+                        moves(&instructions) <= moves(&direct),
+                        "{}: {}",
+                        name,
+                        instructions
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_run_on_a_layout_of_any_size() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::new(vec![
+                vec!['1', '2', '3'],
+                vec!['4', '5', '6'],
+                vec!['7', '8', '9'],
+            ])
+            .unwrap(),
+            position: (1, 1),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("S,R:2,S,D:2,S,L:4,U,S").unwrap();
+        assert_eq!(keyboard.to_string(), "5419");
+
+        keyboard.clear();
+        let instructions = keyboard.generate_instructions("999 123");
+        keyboard.run(&instructions).unwrap();
+        assert_eq!(keyboard.to_string(), "999 123");
+    }
+
+    #[test]
+    fn test_should_select_nothing_on_an_empty_cell() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::new(vec![
+                vec![None, Some('^'), None],
+                vec![Some('<'), Some('O'), Some('>')],
+                vec![None, Some('v')],
+            ])
+            .unwrap(),
+            position: (1, 1),
+            edge_policy: EdgePolicy::Clamp,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("S,U,L,S,D:2,S,R,S,R,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "Ov");
+        assert_eq!(keyboard.position, (2, 2));
+    }
+
+    #[test]
+    fn test_generate_instructions_on_a_sparse_layout() {
+        let text = "TV REMOTE";
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::new(vec![
+                vec![
+                    Some('A'),
+                    Some('B'),
+                    Some('C'),
+                    Some('D'),
+                    Some('E'),
+                    Some('F'),
+                ],
+                vec![Some('G'), None, None, None, None, Some('H')],
+                vec![Some('I'), None, Some('M'), Some('O'), None, Some('R')],
+                vec![Some('T'), Some('V')],
+            ])
+            .unwrap(),
+            position: (3, 1),
+            edge_policy: EdgePolicy::Error,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let instructions = keyboard.generate_instructions(text);
+
+        keyboard.run(&instructions).unwrap();
+        assert_eq!(keyboard.to_string(), text);
+    }
+
+    #[test]
+    fn test_should_select_from_a_layer_for_one_key() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::named("qwerty").unwrap(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("^,S,S,^:2,U:2,S,S,^:0,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "gG=55");
+    }
+
+    #[test]
+    fn test_should_toggle_a_locked_layer() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::named("qwerty").unwrap(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("M,S,S,^:0,S,S,M,S,M:2,S,M:2,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "ggGgGG");
+        assert_eq!(keyboard.layers, LayerState::default());
+    }
+
+    #[test]
+    fn test_should_select_nothing_from_a_missing_layer() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+
+        keyboard.run("^,S,S").unwrap();
+
+        assert_eq!(keyboard.to_string(), "G");
+    }
+
+    #[test]
+    fn test_generate_instructions_with_layers() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::named("qwerty").unwrap(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let instructions = keyboard.generate_instructions("Hi, yo!");
+
+        assert_eq!(
+            instructions,
+            "R,S,^,R:2,U,S,D:2,S,_,M,L:2,D:2,S,R:3,S,R:2,U,S"
+        );
+    }
+
+    #[test]
+    fn test_generate_instructions_with_layers_and_run_them() {
+        let text = "Keyboard Madness, \"version\" {2}\nrun & generate: [OK]?";
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::named("qwerty").unwrap(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState {
+                locked: KeyboardLayout::SYMBOLS,
+                one_shot: Some(KeyboardLayout::SHIFT),
+            },
+            selected_keys: &mut vec![],
+        };
+        let instructions = keyboard.generate_instructions(text);
+
+        keyboard.run(&instructions).unwrap();
+        assert_eq!(keyboard.to_string(), text);
+    }
+
+    #[test]
+    fn test_generate_should_report_untypeable_characters() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let generated = keyboard
+            .generate("HI!\tTHERE~", &GenerateOptions::default())
+            .unwrap();
+
+        keyboard.run_program(&generated.program).unwrap();
+        assert_eq!(keyboard.to_string(), "HITHERE");
+        assert_eq!(
+            generated.untypeable,
+            vec![
+                Untypeable {
+                    index: 2,
+                    key: '!',
+                    substitute: None
+                },
+                Untypeable {
+                    index: 3,
+                    key: '\t',
+                    substitute: None
+                },
+                Untypeable {
+                    index: 9,
+                    key: '~',
+                    substitute: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_should_substitute_untypeable_characters() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let options = GenerateOptions {
+            untypeable: UntypeablePolicy::Substitute(HashMap::from([
+                ('!', '.'),
+                ('\t', ' '),
+                ('~', '!'),
+            ])),
+            ..GenerateOptions::default()
+        };
+        let generated = keyboard.generate("HI!\tTHERE~", &options).unwrap();
+
+        keyboard.run_program(&generated.program).unwrap();
+        assert_eq!(keyboard.to_string(), "HI. THERE");
+        assert_eq!(
+            generated
+                .untypeable
+                .iter()
+                .map(|untypeable| untypeable.substitute)
+                .collect::<Vec<_>>(),
+            vec![Some('.'), Some(' '), None]
+        );
+    }
+
+    #[test]
+    fn test_generate_should_fail_on_the_first_untypeable_character() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KEYS.into(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let options = GenerateOptions {
+            untypeable: UntypeablePolicy::Fail,
+            ..GenerateOptions::default()
+        };
+
+        assert_eq!(
+            keyboard.generate("HI!\tTHERE~", &options),
+            Err(KeyboardError::Untypeable(Untypeable {
+                index: 2,
+                key: '!',
+                substitute: None
+            }))
+        );
+        assert!(keyboard.generate("HI THERE", &options).is_ok());
+    }
+
+    #[test]
+    fn test_generate_should_report_where_the_instructions_end() {
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::named("qwerty").unwrap(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let generated = keyboard
+            .generate("Hey", &GenerateOptions::default())
+            .unwrap();
+
+        assert_eq!(generated.position, (5, 1));
+        assert_eq!(generated.layers.active(), KeyboardLayout::SHIFT);
+        assert_eq!(keyboard.position, (4, 2));
+
+        keyboard.run_program(&generated.program).unwrap();
+        assert_eq!(keyboard.position, generated.position);
+        assert_eq!(keyboard.layers, generated.layers);
+    }
+
+    #[test]
+    fn test_generate_should_advance_the_keyboard_to_chain_instructions() {
+        let text = "It was the best of times,\nit was the WORST of times; {really}.";
+        let mut keyboard = Keyboard {
+            keyboard_layout: KeyboardLayout::named("qwerty").unwrap(),
+            position: (4, 2),
+            edge_policy: EdgePolicy::Error,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let options = GenerateOptions {
+            advance: true,
+            ..GenerateOptions::default()
+        };
+        let mut program = Program::new();
+
+        for word in text.split_inclusive(' ') {
+            let generated = keyboard.generate(word, &options).unwrap();
+            assert_eq!(keyboard.position, generated.position);
+            program.extend(generated.program.instructions().cloned());
+        }
+
+        keyboard.position = (4, 2);
+        keyboard.layers = LayerState::default();
+        keyboard.run(&program.to_string()).unwrap();
+        assert_eq!(keyboard.to_string(), text);
+    }
+
     #[test]
     fn test_generate_instructions_hello() {
         let starting_position: Position = (4, 2);
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: starting_position,
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
         let instructions = keyboard.generate_instructions("HELLO");
 
-        assert_eq!(instructions, "R:1,S,L:3,U:1,S,R:6,D:1,S,S,U:1,S");
+        assert_eq!(instructions, "R,S,L:3,U,S,L:4,D,S,S,U,S");
     }
 
     #[test]
@@ -415,13 +1544,15 @@ mod tests {
         let starting_position: Position = (4, 2);
         let text = "THIS IS A TEST";
         let mut keyboard = Keyboard {
-            keyboard_layout: KEYS,
+            keyboard_layout: KEYS.into(),
             position: starting_position,
+            edge_policy: EdgePolicy::Wrap,
+            layers: LayerState::default(),
             selected_keys: &mut vec![],
         };
         let instructions = keyboard.generate_instructions(text);
 
-        keyboard.run(&instructions);
+        keyboard.run(&instructions).unwrap();
         assert_eq!(keyboard.to_string(), text);
     }
 }
@@ -0,0 +1,335 @@
+use crate::{Direction, EdgePolicy, Instruction, Position, Program};
+
+impl Program {
+    /// Rewrites the program into one with fewer moves that selects the same keys as the original
+    /// when run on a keyboard of the given size, from any starting position.
+    ///
+    /// Moves are merged and zero-count moves and unknown tokens dropped under every
+    /// [`EdgePolicy`]. Under [`EdgePolicy::Wrap`] and [`EdgePolicy::RowWrap`] opposite moves
+    /// also cancel out, and what is left is replaced by the shortest moves to the same key, which
+    /// may go the other way around the keyboard. Moves are never reordered past an `S`, or past
+    /// `_` and `N` under [`EdgePolicy::Error`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{EdgePolicy, Program};
+    ///
+    /// let program: Program = "R,R,R:1,U:0,S,L:2,R:2,S,R:9,S".parse().unwrap();
+    ///
+    /// assert_eq!(program.optimize(EdgePolicy::Wrap, (10, 4)).to_string(), "R:3,S,S,L,S");
+    /// assert_eq!(program.optimize(EdgePolicy::Clamp, (10, 4)).to_string(), "R:3,S,L:2,R:2,S,R:9,S");
+    /// ```
+    pub fn optimize(&self, edge_policy: EdgePolicy, size: (usize, usize)) -> Program {
+        let mut optimized = Program::new();
+        let mut moves = Moves::new(edge_policy, size);
+
+        for instruction in self.instructions() {
+            match *instruction {
+                Instruction::Left(count) => moves.push(Direction::Left, count),
+                Instruction::Up(count) => moves.push(Direction::Up, count),
+                Instruction::Right(count) => moves.push(Direction::Right, count),
+                Instruction::Down(count) => moves.push(Direction::Down, count),
+                Instruction::Unknown(_) => {}
+                Instruction::Space | Instruction::NewLine if edge_policy != EdgePolicy::Error => {
+                    optimized.push(instruction.clone())
+                }
+                Instruction::Shift(_) | Instruction::Lock(_) => optimized.push(instruction.clone()),
+                Instruction::Space | Instruction::NewLine | Instruction::Select => {
+                    moves.flush(&mut optimized);
+                    optimized.push(instruction.clone());
+                }
+            }
+        }
+        moves.flush(&mut optimized);
+
+        optimized
+    }
+}
+
+/// The moves since the last barrier, kept in the most compact form the edge policy allows.
+struct Moves {
+    edge_policy: EdgePolicy,
+    size: (usize, usize),
+    /// Where the moves take a cursor starting from the top left corner, for the wrapping edge
+    /// policies.
+    offset: Position,
+    /// The horizontal and vertical moves, with runs in the same direction merged, for the
+    /// others.
+    horizontal: Vec<(Direction, usize)>,
+    vertical: Vec<(Direction, usize)>,
+}
+
+impl Moves {
+    fn new(edge_policy: EdgePolicy, size: (usize, usize)) -> Self {
+        Moves {
+            edge_policy,
+            size,
+            offset: (0, 0),
+            horizontal: vec![],
+            vertical: vec![],
+        }
+    }
+
+    fn push(&mut self, direction: Direction, count: usize) {
+        match self.edge_policy {
+            EdgePolicy::Wrap | EdgePolicy::RowWrap => {
+                self.offset = self
+                    .edge_policy
+                    .step(self.offset, direction, count, self.size)
+                    .expect("wrapping moves never fail");
+            }
+            EdgePolicy::Clamp | EdgePolicy::Error => {
+                let (moves, len) = match direction {
+                    Direction::Left | Direction::Right => (&mut self.horizontal, self.size.0),
+                    Direction::Up | Direction::Down => (&mut self.vertical, self.size.1),
+                };
+
+                match moves.last_mut() {
+                    Some((last, total)) if *last == direction => {
+                        *total = total.saturating_add(count)
+                    }
+                    _ => moves.push((direction, count)),
+                }
+
+                // Clamping can never take the cursor further than the far edge.
+                if let (EdgePolicy::Clamp, Some((_, total))) = (self.edge_policy, moves.last_mut())
+                {
+                    *total = (*total).min(len - 1);
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self, program: &mut Program) {
+        self.take().into_iter().for_each(|(direction, count)| {
+            program.push(match direction {
+                Direction::Left => Instruction::Left(count),
+                Direction::Up => Instruction::Up(count),
+                Direction::Right => Instruction::Right(count),
+                Direction::Down => Instruction::Down(count),
+            })
+        });
+    }
+
+    /// Takes the moves as the fewest steps the edge policy allows, leaving none behind.
+    fn take(&mut self) -> Vec<(Direction, usize)> {
+        let (width, height) = self.size;
+        let (x, y) = std::mem::take(&mut self.offset);
+
+        let moves = match self.edge_policy {
+            EdgePolicy::Wrap => vec![
+                shortest(x, width, Direction::Right, Direction::Left),
+                shortest(y, height, Direction::Down, Direction::Up),
+            ],
+            EdgePolicy::RowWrap => {
+                let len = width * height;
+                let index = y * width + x;
+
+                // Every vertical move goes a whole row along, so try each number of rows and
+                // make up the rest with horizontal moves.
+                (0..height)
+                    .map(|rows| {
+                        let rest = (index + len - rows * width) % len;
+                        [
+                            shortest(rest, len, Direction::Right, Direction::Left),
+                            shortest(rows, height, Direction::Down, Direction::Up),
+                        ]
+                    })
+                    .min_by_key(|moves| {
+                        let total: usize = moves.iter().map(|&(_, count)| count).sum();
+                        let steps = moves.iter().filter(|&&(_, count)| count > 0).count();
+                        (total, steps)
+                    })
+                    .expect("a layout has at least one row")
+                    .to_vec()
+            }
+            EdgePolicy::Clamp | EdgePolicy::Error => self
+                .horizontal
+                .drain(..)
+                .chain(self.vertical.drain(..))
+                .collect(),
+        };
+
+        moves.into_iter().filter(|&(_, count)| count > 0).collect()
+    }
+}
+
+/// The shortest way to go `offset` forward around a loop of `len`, either forward or backward.
+fn shortest(
+    offset: usize,
+    len: usize,
+    forward: Direction,
+    backward: Direction,
+) -> (Direction, usize) {
+    if offset <= len - offset {
+        (forward, offset)
+    } else {
+        (backward, len - offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        EdgePolicy, Instruction, Keyboard, KeyboardLayout, LayerState, ParseMode, Program,
+    };
+
+    const POLICIES: [EdgePolicy; 4] = [
+        EdgePolicy::Wrap,
+        EdgePolicy::Clamp,
+        EdgePolicy::Error,
+        EdgePolicy::RowWrap,
+    ];
+
+    fn run(
+        layout: &KeyboardLayout,
+        edge_policy: EdgePolicy,
+        position: (usize, usize),
+        program: &Program,
+    ) -> (String, Option<(usize, usize)>) {
+        let mut keyboard = Keyboard {
+            keyboard_layout: layout.clone(),
+            position,
+            edge_policy,
+            layers: LayerState::default(),
+            selected_keys: &mut vec![],
+        };
+        let result = keyboard.run_program(program);
+        let position = result.ok().map(|_| keyboard.position);
+
+        (keyboard.to_string(), position)
+    }
+
+    fn assert_optimized(layout: &KeyboardLayout, program: &Program) {
+        for edge_policy in POLICIES {
+            let optimized = program.optimize(edge_policy, layout.size());
+            let moves = |program: &Program| -> usize {
+                program
+                    .instructions()
+                    .map(|instruction| match *instruction {
+                        Instruction::Left(count)
+                        | Instruction::Up(count)
+                        | Instruction::Right(count)
+                        | Instruction::Down(count) => count,
+                        _ => 0,
+                    })
+                    .sum()
+            };
+
+            assert!(
+                moves(&optimized) <= moves(program),
+                "{:?}: {} -> {}",
+                edge_policy,
+                program,
+                optimized
+            );
+
+            for y in 0..layout.height() {
+                for x in 0..layout.width() {
+                    assert_eq!(
+                        run(layout, edge_policy, (x, y), &optimized),
+                        run(layout, edge_policy, (x, y), program),
+                        "{:?} from ({}, {}): {} -> {}",
+                        edge_policy,
+                        x,
+                        y,
+                        program,
+                        optimized
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_merge_and_cancel_moves() {
+        let program = Program::parse(
+            "R,R,R,S,L:2,R:2,S,U:0,R:1,D,D,S,Testing",
+            ParseMode::Lenient,
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.optimize(EdgePolicy::Wrap, (10, 4)).to_string(),
+            "R:3,S,S,R,D:2,S"
+        );
+        assert_eq!(
+            program.optimize(EdgePolicy::Error, (10, 4)).to_string(),
+            "R:3,S,L:2,R:2,S,R,D:2,S"
+        );
+    }
+
+    #[test]
+    fn test_should_move_the_short_way_around() {
+        let program: Program = "R:7,S,U:3,S,L:23,S,D:4,S".parse().unwrap();
+
+        assert_eq!(
+            program.optimize(EdgePolicy::Wrap, (10, 4)).to_string(),
+            "L:3,S,D,S,L:3,S,S"
+        );
+        assert_eq!(
+            program.optimize(EdgePolicy::RowWrap, (10, 4)).to_string(),
+            "L:3,D,S,D,S,L:3,D:2,S,S"
+        );
+        assert_eq!(
+            program.optimize(EdgePolicy::Clamp, (10, 4)).to_string(),
+            "R:7,S,U:3,S,L:9,S,D:3,S"
+        );
+    }
+
+    #[test]
+    fn test_should_use_whole_rows_for_row_wrap() {
+        let program: Program = "R:19,S,L:11,S".parse().unwrap();
+
+        assert_eq!(
+            program.optimize(EdgePolicy::RowWrap, (10, 4)).to_string(),
+            "L,D:2,S,L,U,S"
+        );
+    }
+
+    #[test]
+    fn test_should_only_move_moves_past_output_when_they_cannot_fail() {
+        let program: Program = "R,_,R,N,^:1,R,S".parse().unwrap();
+
+        assert_eq!(
+            program.optimize(EdgePolicy::Clamp, (10, 4)).to_string(),
+            "_,N,^,R:3,S"
+        );
+        assert_eq!(
+            program.optimize(EdgePolicy::Error, (10, 4)).to_string(),
+            "R,_,R,N,^,R,S"
+        );
+    }
+
+    #[test]
+    fn test_should_select_the_same_keys_after_optimizing() {
+        let layout = KeyboardLayout::from([['A', 'B', 'C'], ['D', 'E', 'F']])
+            .with_layer([['a', 'b', 'c'], ['d', 'e', 'f']]);
+        let mut seed = 0x2545_f491_u64;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound) as usize
+        };
+
+        for _ in 0..200 {
+            let program: Program = (0..next(12))
+                .map(|_| match next(10) {
+                    0 => Instruction::Left(next(8)),
+                    1 => Instruction::Up(next(5)),
+                    2 => Instruction::Right(next(8)),
+                    3 => Instruction::Down(next(5)),
+                    4 => Instruction::Space,
+                    5 => Instruction::NewLine,
+                    6 => Instruction::Shift(1),
+                    7 => Instruction::Lock(1),
+                    _ => Instruction::Select,
+                })
+                .collect();
+
+            assert_optimized(&layout, &program);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::io;
+
+/// Where a [`Keyboard`](crate::Keyboard) puts the keys it selects, one at a time as they are
+/// selected.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{CountingSink, Keyboard, KEYS};
+///
+/// let mut keyboard = Keyboard::builder(KEYS.into())
+///     .position((4, 2))
+///     .build_with(CountingSink::default());
+///
+/// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
+/// assert_eq!(keyboard.selected_keys.count, 5);
+/// ```
+pub trait OutputSink {
+    fn push(&mut self, key: char);
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for &mut S {
+    fn push(&mut self, key: char) {
+        (**self).push(key)
+    }
+}
+
+impl OutputSink for Vec<char> {
+    fn push(&mut self, key: char) {
+        Vec::push(self, key)
+    }
+}
+
+impl OutputSink for String {
+    fn push(&mut self, key: char) {
+        String::push(self, key)
+    }
+}
+
+/// Counts the selected keys without keeping them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CountingSink {
+    pub count: usize,
+}
+
+impl OutputSink for CountingSink {
+    fn push(&mut self, _: char) {
+        self.count += 1;
+    }
+}
+
+/// Throws the selected keys away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+    fn push(&mut self, _: char) {}
+}
+
+/// Writes the selected keys to an [`io::Write`] as UTF-8 as they are selected.
+///
+/// Selecting a key cannot fail, so the first error writing is kept for [`WriteSink::finish`]
+/// and nothing more is written after it.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{Keyboard, WriteSink, KEYS};
+///
+/// let mut keyboard = Keyboard::builder(KEYS.into())
+///     .position((4, 2))
+///     .build_with(WriteSink::new(vec![]));
+///
+/// keyboard.run("R,S,U,L:3,S,D,R:6,S,S,U,S").unwrap();
+/// assert_eq!(keyboard.selected_keys.finish().unwrap(), b"HELLO");
+/// ```
+#[derive(Debug)]
+pub struct WriteSink<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+    written: usize,
+}
+
+impl<W: io::Write> WriteSink<W> {
+    pub fn new(writer: W) -> Self {
+        WriteSink {
+            writer,
+            error: None,
+            written: 0,
+        }
+    }
+
+    /// How many keys have been written.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// The first error writing, if there has been one.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Flushes the writer and gives it back, or the first error writing to it.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => {
+                self.writer.flush()?;
+                Ok(self.writer)
+            }
+        }
+    }
+}
+
+impl<W: io::Write> OutputSink for WriteSink<W> {
+    fn push(&mut self, key: char) {
+        if self.error.is_none() {
+            let mut buffer = [0; 4];
+            if let Err(err) = self
+                .writer
+                .write_all(key.encode_utf8(&mut buffer).as_bytes())
+            {
+                self.error = Some(err);
+            } else {
+                self.written += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::*;
+    use crate::{Keyboard, KEYS};
+
+    /// Accepts `capacity` bytes, then fails.
+    struct Limited {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Write for Limited {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.len() + buf.len() > self.capacity {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_should_push_to_every_sink() {
+        let instructions = "R,S,U,L:3,S,D,R:6,S,S,U,S,_,N";
+
+        let mut keyboard = Keyboard::builder(KEYS.into())
+            .position((4, 2))
+            .build_with(String::new());
+        keyboard.run(instructions).unwrap();
+        assert_eq!(keyboard.selected_keys, "HELLO \n");
+
+        let mut keys = vec![];
+        let mut keyboard = Keyboard::builder(KEYS.into())
+            .position((4, 2))
+            .build_with(&mut keys);
+        keyboard.run(instructions).unwrap();
+        assert_eq!(keys.len(), 7);
+
+        let mut keyboard = Keyboard::builder(KEYS.into())
+            .position((4, 2))
+            .build_with(NullSink);
+        keyboard.run(instructions).unwrap();
+        assert_eq!(keyboard.position, (8, 1));
+    }
+
+    #[test]
+    fn test_should_write_utf8_and_keep_the_first_error() {
+        let mut sink = WriteSink::new(Limited {
+            written: vec![],
+            capacity: 3,
+        });
+
+        sink.push('é');
+        sink.push('!');
+        assert!(sink.error().is_none());
+        sink.push('é');
+        sink.push('!');
+        assert_eq!(sink.writer.written, "é!".as_bytes());
+        assert_eq!(sink.written(), 2);
+        assert_eq!(
+            sink.finish().map(|_| ()).unwrap_err().kind(),
+            io::ErrorKind::WriteZero
+        );
+    }
+}
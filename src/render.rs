@@ -0,0 +1,66 @@
+use std::borrow::BorrowMut;
+
+use crate::Keyboard;
+
+/// Starts highlighting the key under the cursor, in reverse video.
+const HIGHLIGHT: &str = "\x1b[7m";
+/// Stops highlighting.
+const RESET: &str = "\x1b[0m";
+
+impl<O: BorrowMut<Vec<char>>> Keyboard<O> {
+    /// Draws the keyboard for a terminal: the keys on the layer the next `S` selects from, with
+    /// the key under the cursor highlighted using ANSI escape codes, then the keys selected so
+    /// far beneath it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{Keyboard, KeyboardLayout};
+    ///
+    /// let layout = KeyboardLayout::from([['A', 'B'], ['C', 'D']]);
+    /// let mut keyboard = Keyboard::builder(layout).build();
+    /// keyboard.run("R,S").unwrap();
+    ///
+    /// assert_eq!(keyboard.render(), " A \x1b[7m B \x1b[0m\n C  D \n\nB");
+    /// ```
+    pub fn render(&self) -> String {
+        let (width, height) = self.keyboard_layout.size();
+        let layer = self.layers.active();
+        let mut rendered = String::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                let key = self.keyboard_layout.get_on(layer, (x, y)).unwrap_or(' ');
+                if (x, y) == self.position {
+                    rendered.extend([HIGHLIGHT, " ", &key.to_string(), " ", RESET]);
+                } else {
+                    rendered.extend([" ", &key.to_string(), " "]);
+                }
+            }
+            rendered.push('\n');
+        }
+        rendered.push('\n');
+        rendered.extend(self.output());
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Keyboard, KeyboardLayout};
+
+    #[test]
+    fn test_should_render_the_active_layer_and_empty_cells() {
+        let layout = KeyboardLayout::new([[Some('a'), None], [Some('c'), Some('d')]])
+            .unwrap()
+            .with_layer([['A', 'B'], ['C', 'D']]);
+        let mut keyboard = Keyboard::builder(layout).build();
+
+        keyboard.run("R").unwrap();
+        assert_eq!(keyboard.render(), " a \x1b[7m   \x1b[0m\n c  d \n\n");
+
+        keyboard.run("^,D,_").unwrap();
+        assert_eq!(keyboard.render(), " A  B \n C \x1b[7m D \x1b[0m\n\n ");
+    }
+}
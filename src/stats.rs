@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    CostModel, Event, Instruction, Keyboard, KeyboardError, Keystrokes, OutputSink, Program,
+};
+
+/// Counts of what a program did when it ran, for comparing layouts.
+///
+/// # Examples
+///
+/// ```
+/// use keyboard_madness::{Keyboard, Program, Stats, KEYS};
+///
+/// let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+/// let program: Program = "R,S,U,L:3,S,D,L:4,S,S,U,S".parse().unwrap();
+/// let stats = keyboard.stats(&program).unwrap();
+///
+/// assert_eq!((stats.moves, stats.distance, stats.presses), (6, 11, 16));
+/// assert_eq!(stats.wraps, 1);
+/// assert_eq!(stats.selected[&'L'], 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct Stats {
+    /// How many move instructions ran.
+    pub moves: usize,
+    /// How many keys the cursor travelled across, going around the edges when it wrapped.
+    pub distance: usize,
+    /// How many buttons were pressed, counting `R:3` as 3.
+    pub presses: usize,
+    /// How many moves wrapped around an edge.
+    pub wraps: usize,
+    /// How many characters were typed, including spaces and new lines.
+    pub selections: usize,
+    /// How many unknown instructions were ignored.
+    pub unknown: usize,
+    /// How many times each character was typed.
+    pub selected: BTreeMap<char, usize>,
+    /// How many moves ended on each key, on the layer the next `S` selects from.
+    pub visited: BTreeMap<char, usize>,
+}
+
+impl Stats {
+    /// Adds what one instruction did, as reported by [`Keyboard::execute`].
+    pub fn record(&mut self, event: &Event) {
+        match event.instruction {
+            Instruction::Left(count)
+            | Instruction::Up(count)
+            | Instruction::Right(count)
+            | Instruction::Down(count) => {
+                self.moves += 1;
+                self.distance += if event.wrapped {
+                    count
+                } else {
+                    event.before.0.abs_diff(event.after.0) + event.before.1.abs_diff(event.after.1)
+                };
+                if event.wrapped {
+                    self.wraps += 1;
+                }
+                if let Some(key) = event.key {
+                    *self.visited.entry(key).or_default() += 1;
+                }
+            }
+            Instruction::Unknown(_) => self.unknown += 1,
+            _ => {}
+        }
+
+        if !matches!(event.instruction, Instruction::Unknown(_)) {
+            self.presses += Keystrokes.cost(&event.instruction);
+        }
+        if let Some(key) = event.emitted {
+            self.selections += 1;
+            *self.selected.entry(key).or_default() += 1;
+        }
+    }
+}
+
+impl<O: OutputSink> Keyboard<O> {
+    /// Runs a program and counts what it did. To keep the counts up to an error, use
+    /// [`Keyboard::trace`] and [`Stats::record`] instead.
+    pub fn stats(&mut self, program: &Program) -> Result<Stats, KeyboardError> {
+        let mut stats = Stats::default();
+        for event in self.trace(program) {
+            stats.record(&event?);
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EdgePolicy, Keyboard, ParseMode, Program, KEYS};
+
+    #[test]
+    fn test_should_count_presses_and_unknown_instructions() {
+        let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+        let program = Program::parse("R:3,X,S,_,N,S,U:0,Y", ParseMode::Lenient).unwrap();
+        let stats = keyboard.stats(&program).unwrap();
+
+        assert_eq!(stats.moves, 2);
+        assert_eq!(stats.distance, 3);
+        assert_eq!(stats.presses, 7);
+        assert_eq!(stats.unknown, 2);
+        assert_eq!(stats.selections, 4);
+        assert_eq!(stats.selected.keys().collect::<String>(), "\n K");
+        assert_eq!(stats.selected[&'K'], 2);
+        assert_eq!(
+            stats.visited.into_iter().collect::<Vec<_>>(),
+            vec![('K', 2)]
+        );
+    }
+
+    #[test]
+    fn test_should_only_count_the_distance_travelled() {
+        let program: Program = "L:12,S,R:30,S".parse().unwrap();
+
+        let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+        let stats = keyboard.stats(&program).unwrap();
+        assert_eq!((stats.distance, stats.presses, stats.wraps), (42, 44, 2));
+
+        let mut keyboard = Keyboard::builder(KEYS.into())
+            .position((4, 2))
+            .edge_policy(EdgePolicy::Clamp)
+            .build();
+        let stats = keyboard.stats(&program).unwrap();
+        assert_eq!((stats.distance, stats.presses, stats.wraps), (13, 44, 0));
+        assert_eq!(
+            stats.visited.into_iter().collect::<Vec<_>>(),
+            vec![(';', 1), ('A', 1)]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::{iter::Enumerate, slice};
+
+use crate::{
+    Instruction, Keyboard, KeyboardError, LayerState, OutputSink, Position, Program, Spanned,
+};
+
+/// What running a single instruction did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Which instruction of the program it was, counting from 0.
+    pub index: usize,
+    pub instruction: Instruction,
+    /// Where the cursor was before the instruction.
+    pub before: Position,
+    /// Where the cursor is after the instruction.
+    pub after: Position,
+    /// The key under the cursor afterwards, on the layer the next `S` selects from.
+    pub key: Option<char>,
+    /// The character the instruction added to the output, if any.
+    pub emitted: Option<char>,
+    /// Whether a move wrapped around an edge of the keyboard.
+    pub wrapped: bool,
+    /// The layers after the instruction.
+    pub layers: LayerState,
+}
+
+/// An iterator running a program an instruction at a time. See [`Keyboard::trace`].
+pub struct Trace<'a, O> {
+    keyboard: &'a mut Keyboard<O>,
+    steps: Enumerate<slice::Iter<'a, Spanned>>,
+    failed: bool,
+}
+
+impl<'a, O: OutputSink> Trace<'a, O> {
+    pub(crate) fn new(keyboard: &'a mut Keyboard<O>, program: &'a Program) -> Self {
+        Trace {
+            keyboard,
+            steps: program.steps().iter().enumerate(),
+            failed: false,
+        }
+    }
+}
+
+impl<O: OutputSink> Iterator for Trace<'_, O> {
+    type Item = Result<Event, KeyboardError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let (index, step) = self.steps.next()?;
+        let event = self.keyboard.execute(index, &step.instruction);
+        self.failed = event.is_err();
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        EdgePolicy, Event, Instruction, Keyboard, KeyboardError, LayerState, ParseMode, Program,
+        KEYS,
+    };
+
+    #[test]
+    fn test_should_trace_every_instruction() {
+        let mut keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+        let program = Program::parse("R,S,^,_,L:7,X", ParseMode::Lenient).unwrap();
+        let events: Vec<Event> = keyboard.trace(&program).map(Result::unwrap).collect();
+
+        assert_eq!(
+            events[0],
+            Event {
+                index: 0,
+                instruction: Instruction::Right(1),
+                before: (4, 2),
+                after: (5, 2),
+                key: Some('H'),
+                emitted: None,
+                wrapped: false,
+                layers: LayerState::default(),
+            }
+        );
+        assert_eq!(events[1].emitted, Some('H'));
+        assert_eq!(events[2].key, None);
+        assert_eq!(events[2].layers.one_shot, Some(1));
+        assert_eq!(events[3].emitted, Some(' '));
+        assert_eq!((events[4].after, events[4].wrapped), ((8, 2), true));
+        assert_eq!(events[5].instruction, Instruction::Unknown("X".to_string()));
+        assert_eq!(events.len(), 6);
+    }
+
+    #[test]
+    fn test_should_only_wrap_under_the_wrapping_edge_policies() {
+        for (edge_policy, after, wrapped) in [
+            (EdgePolicy::Wrap, (1, 1), true),
+            (EdgePolicy::RowWrap, (1, 2), true),
+            (EdgePolicy::Clamp, (9, 1), false),
+        ] {
+            let mut keyboard = Keyboard::builder(KEYS.into())
+                .position((8, 1))
+                .edge_policy(edge_policy)
+                .build();
+            let event = keyboard.execute(0, &Instruction::Right(3)).unwrap();
+
+            assert_eq!(
+                (event.after, event.wrapped),
+                (after, wrapped),
+                "{:?}",
+                edge_policy
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_stop_tracing_at_the_first_error() {
+        let mut keyboard = Keyboard::builder(KEYS.into())
+            .position((4, 2))
+            .edge_policy(EdgePolicy::Error)
+            .build();
+        let program: Program = "S,D:2,S".parse().unwrap();
+        let events: Vec<_> = keyboard.trace(&program).collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            Err(KeyboardError::OutOfBounds {
+                index: 1,
+                position: (4, 2)
+            })
+        );
+        assert_eq!(keyboard.to_string(), "G");
+    }
+}
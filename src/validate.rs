@@ -0,0 +1,379 @@
+use std::{fmt, ops::Range};
+
+use crate::{
+    instruction::parse_token, Direction, EdgePolicy, Instruction, Keyboard, KeyboardError,
+    NullSink, ParseErrorKind, ParseMode, Program,
+};
+
+/// How bad a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The instructions run, but probably not as meant.
+    Warning,
+    /// The instructions are rejected by `--strict`, or stop with an error when run.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What is wrong with an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// The token is not an instruction.
+    UnknownInstruction(String),
+    /// The token starts with an instruction but has more after it.
+    TrailingInput(String),
+    /// The count is too large to represent.
+    CountOverflow,
+    /// A move with a count of 0 does nothing.
+    ZeroCount,
+    /// A move goes at least as far as the keyboard is `len` keys across.
+    CountExceedsLayout { count: usize, len: usize },
+    /// A move stops at the edge under [`EdgePolicy::Clamp`] before going `count` keys.
+    ClampedMove { count: usize, moved: usize },
+    /// A move runs off the edge under [`EdgePolicy::Error`], and nothing after it runs.
+    OutOfBounds,
+    /// `S` on a key with nothing on the layer it selects from.
+    EmptySelect,
+    /// A move that can be merged with the move at `previous`, as nothing is selected between.
+    RedundantMove { previous: usize },
+}
+
+impl FindingKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            FindingKind::UnknownInstruction(_)
+            | FindingKind::TrailingInput(_)
+            | FindingKind::CountOverflow
+            | FindingKind::OutOfBounds => Severity::Error,
+            FindingKind::ZeroCount
+            | FindingKind::CountExceedsLayout { .. }
+            | FindingKind::ClampedMove { .. }
+            | FindingKind::EmptySelect
+            | FindingKind::RedundantMove { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FindingKind::UnknownInstruction(token) if token.is_empty() => {
+                write!(f, "empty instruction")
+            }
+            FindingKind::UnknownInstruction(token) => write!(f, "unknown instruction `{}`", token),
+            FindingKind::TrailingInput(token) => {
+                write!(f, "unexpected input after instruction in `{}`", token)
+            }
+            FindingKind::CountOverflow => write!(f, "count is too large"),
+            FindingKind::ZeroCount => write!(f, "move with a count of 0 does nothing"),
+            FindingKind::CountExceedsLayout { count, len } => write!(
+                f,
+                "move of {} is at least the {} keys across the keyboard",
+                count, len
+            ),
+            FindingKind::ClampedMove { count, moved } => {
+                write!(f, "move of {} stops at the edge after {}", count, moved)
+            }
+            FindingKind::OutOfBounds => write!(f, "move runs off the keyboard"),
+            FindingKind::EmptySelect => write!(f, "selects an empty key"),
+            FindingKind::RedundantMove { previous } => {
+                write!(f, "move can be merged with instruction {}", previous)
+            }
+        }
+    }
+}
+
+/// Something wrong with instruction `index`, found at byte range `span` of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub index: usize,
+    pub span: Range<usize>,
+    pub kind: FindingKind,
+}
+
+impl Finding {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: instruction {} at bytes {}..{}: {}",
+            self.severity(),
+            self.index,
+            self.span.start,
+            self.span.end,
+            self.kind
+        )
+    }
+}
+
+impl<O> Keyboard<O> {
+    /// Checks instructions for mistakes, running them from where the keyboard is without
+    /// changing it. Running stops at the first move off the keyboard under
+    /// [`EdgePolicy::Error`], but every instruction is still checked on its own.
+    ///
+    /// Findings are in order of the instruction they are about.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use keyboard_madness::{FindingKind, Keyboard, Severity, KEYS};
+    ///
+    /// let keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+    /// let findings = keyboard.validate("R,S,RX,R,U:0,S");
+    ///
+    /// assert_eq!(findings[0].index, 2);
+    /// assert_eq!(findings[0].severity(), Severity::Error);
+    /// assert_eq!(findings[1].kind, FindingKind::RedundantMove { previous: 2 });
+    /// assert_eq!(findings[2].kind, FindingKind::ZeroCount);
+    /// ```
+    pub fn validate(&self, input: &str) -> Vec<Finding> {
+        let program =
+            Program::parse(input, ParseMode::Lenient).expect("lenient parsing never fails");
+        let mut keyboard = Keyboard {
+            keyboard_layout: self.keyboard_layout.clone(),
+            position: self.position,
+            edge_policy: self.edge_policy,
+            layers: self.layers,
+            selected_keys: NullSink,
+        };
+        let (width, height) = keyboard.keyboard_layout.size();
+        let mut running = true;
+        // The moves since the last instruction that selects or types a key.
+        let mut moves: Vec<(usize, Direction)> = vec![];
+        let mut findings = vec![];
+
+        for (index, step) in program.steps().iter().enumerate() {
+            let span = step.span.clone().expect("parsed instructions have spans");
+            let token = &input[span.clone()];
+            let mut found = |kind| {
+                findings.push(Finding {
+                    index,
+                    span: span.clone(),
+                    kind,
+                })
+            };
+
+            match parse_token(token, ParseMode::Strict) {
+                Ok(_) => {}
+                Err(ParseErrorKind::Unknown) => {
+                    found(FindingKind::UnknownInstruction(token.to_string()))
+                }
+                Err(ParseErrorKind::TrailingInput) => {
+                    found(FindingKind::TrailingInput(token.to_string()))
+                }
+                Err(ParseErrorKind::CountOverflow) => found(FindingKind::CountOverflow),
+            }
+
+            let instruction = &step.instruction;
+            if let Some((direction, count)) = movement(instruction) {
+                let len = match (direction, self.edge_policy) {
+                    (Direction::Left | Direction::Right, EdgePolicy::RowWrap) => width * height,
+                    (Direction::Left | Direction::Right, _) => width,
+                    (Direction::Up | Direction::Down, _) => height,
+                };
+                if count == 0 {
+                    found(FindingKind::ZeroCount);
+                } else if count >= len {
+                    found(FindingKind::CountExceedsLayout { count, len });
+                }
+
+                // Without wrapping, a move only merges with moves the same way, and never across a
+                // move back the other way, as either could stop at or run off the edge.
+                let wraps = matches!(self.edge_policy, EdgePolicy::Wrap | EdgePolicy::RowWrap);
+                let previous = moves
+                    .iter()
+                    .find(|&&(_, previous)| horizontal(previous) == horizontal(direction));
+                match previous {
+                    Some(&(previous, other)) if wraps || other == direction => {
+                        found(FindingKind::RedundantMove { previous })
+                    }
+                    _ => {}
+                }
+                if count > 0 {
+                    if !wraps {
+                        moves.retain(|&(_, previous)| {
+                            previous == direction || horizontal(previous) != horizontal(direction)
+                        });
+                    }
+                    moves.push((index, direction));
+                }
+            } else if !matches!(
+                instruction,
+                Instruction::Shift(_) | Instruction::Lock(_) | Instruction::Unknown(_)
+            ) {
+                moves.clear();
+            }
+
+            if !running {
+                continue;
+            }
+            match keyboard.execute(index, instruction) {
+                Ok(event) => match (instruction, movement(instruction)) {
+                    (Instruction::Select, _) if event.emitted.is_none() => {
+                        found(FindingKind::EmptySelect)
+                    }
+                    (_, Some((_, count))) if self.edge_policy == EdgePolicy::Clamp => {
+                        let moved = event.before.0.abs_diff(event.after.0)
+                            + event.before.1.abs_diff(event.after.1);
+                        if moved < count {
+                            found(FindingKind::ClampedMove { count, moved });
+                        }
+                    }
+                    _ => {}
+                },
+                Err(KeyboardError::OutOfBounds { .. }) => {
+                    found(FindingKind::OutOfBounds);
+                    running = false;
+                }
+                Err(_) => running = false,
+            }
+        }
+
+        findings
+    }
+}
+
+/// The direction and count of a move.
+fn movement(instruction: &Instruction) -> Option<(Direction, usize)> {
+    match *instruction {
+        Instruction::Left(count) => Some((Direction::Left, count)),
+        Instruction::Up(count) => Some((Direction::Up, count)),
+        Instruction::Right(count) => Some((Direction::Right, count)),
+        Instruction::Down(count) => Some((Direction::Down, count)),
+        _ => None,
+    }
+}
+
+/// Whether a direction is horizontal.
+fn horizontal(direction: Direction) -> bool {
+    matches!(direction, Direction::Left | Direction::Right)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EdgePolicy, FindingKind, Keyboard, KeyboardLayout, Severity, KEYS};
+
+    fn kinds(keyboard: &Keyboard, input: &str) -> Vec<(usize, FindingKind)> {
+        keyboard
+            .validate(input)
+            .into_iter()
+            .map(|finding| (finding.index, finding.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_should_find_problems_with_tokens() {
+        let keyboard = Keyboard::builder(KEYS.into()).position((4, 2)).build();
+
+        assert_eq!(
+            kinds(&keyboard, "R,Testing,,SX,R:99999999999999999999,D:4,L:10,S"),
+            vec![
+                (1, FindingKind::UnknownInstruction("Testing".to_string())),
+                (2, FindingKind::UnknownInstruction("".to_string())),
+                (3, FindingKind::TrailingInput("SX".to_string())),
+                (4, FindingKind::CountOverflow),
+                (5, FindingKind::CountExceedsLayout { count: 4, len: 4 }),
+                (6, FindingKind::CountExceedsLayout { count: 10, len: 10 }),
+            ]
+        );
+        assert!(kinds(&keyboard, "R,S,U,L:3,S,D,R:6,S,S,U,S").is_empty());
+        assert_eq!(
+            kinds(&keyboard, "S:3,_:9,N:0"),
+            vec![
+                (0, FindingKind::TrailingInput("S:3".to_string())),
+                (1, FindingKind::TrailingInput("_:9".to_string())),
+                (2, FindingKind::TrailingInput("N:0".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_find_moves_that_can_be_merged() {
+        let qwerty = KeyboardLayout::named("qwerty").unwrap();
+        let keyboard = Keyboard::builder(qwerty).position((4, 2)).build();
+        assert_eq!(
+            kinds(&keyboard, "R,U,^,R,S,L,_,R"),
+            vec![(3, FindingKind::RedundantMove { previous: 0 })]
+        );
+        assert_eq!(
+            kinds(&keyboard, "R,U,L,S"),
+            vec![(2, FindingKind::RedundantMove { previous: 0 })]
+        );
+
+        // Clamping can stop a move at the edge, so going back is not the same as not moving.
+        let keyboard = Keyboard::builder(KEYS.into())
+            .position((4, 2))
+            .edge_policy(EdgePolicy::Clamp)
+            .build();
+        assert!(kinds(&keyboard, "R,U,L,S").is_empty());
+
+        // Merging these into `R:2,L` would stop at the edge and type another key.
+        let keyboard = Keyboard::builder(KEYS.into())
+            .position((9, 2))
+            .edge_policy(EdgePolicy::Clamp)
+            .build();
+        assert!(!kinds(&keyboard, "R,L,R,S")
+            .iter()
+            .any(|(_, kind)| matches!(kind, FindingKind::RedundantMove { .. })));
+        assert_eq!(
+            kinds(&keyboard, "R,L,U,L,S"),
+            vec![
+                (0, FindingKind::ClampedMove { count: 1, moved: 0 }),
+                (3, FindingKind::RedundantMove { previous: 1 })
+            ]
+        );
+
+        let keyboard = Keyboard::builder(KEYS.into())
+            .position((8, 2))
+            .edge_policy(EdgePolicy::Error)
+            .build();
+        assert!(kinds(&keyboard, "R,L,R,S").is_empty());
+    }
+
+    #[test]
+    fn test_should_find_problems_running() {
+        let layout = KeyboardLayout::new([[Some('A'), None], [Some('C'), Some('D')]]).unwrap();
+
+        let keyboard = Keyboard::builder(layout.clone())
+            .edge_policy(EdgePolicy::Clamp)
+            .build();
+        assert_eq!(
+            kinds(&keyboard, "L,S,R,S,^,D,S"),
+            vec![
+                (0, FindingKind::ClampedMove { count: 1, moved: 0 }),
+                (3, FindingKind::EmptySelect),
+                (6, FindingKind::EmptySelect),
+            ]
+        );
+
+        let keyboard = Keyboard::builder(layout)
+            .edge_policy(EdgePolicy::Error)
+            .build();
+        let findings = keyboard.validate("R,S,U,S,X");
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| (finding.index, finding.severity()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, Severity::Warning),
+                (2, Severity::Error),
+                (4, Severity::Error)
+            ]
+        );
+        assert_eq!(keyboard.position, (0, 0));
+    }
+}